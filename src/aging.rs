@@ -0,0 +1,172 @@
+use bevy::utils::HashMap;
+use serde::Deserialize;
+
+use crate::prelude::*;
+
+const AGE_STRUCTURE_LOG_INTERVAL: f32 = 30.0;
+
+pub struct AgingPlugin;
+
+impl Plugin for AgingPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(AgeStructureLogTimer(Timer::from_seconds(
+            AGE_STRUCTURE_LOG_INTERVAL,
+            true,
+        )))
+        .add_system(Self::grow_older)
+        .add_system(Self::update_life_stages)
+        .add_system(Self::die_of_old_age)
+        .add_system(Self::log_age_structure);
+    }
+}
+
+struct AgeStructureLogTimer(Timer);
+
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq, Hash, EnumIter)]
+pub enum LifeStage {
+    Newborn,
+    Juvenile,
+    Adult,
+    Elderly,
+}
+
+impl LifeStage {
+    pub fn size_factor(&self) -> f32 {
+        match self {
+            LifeStage::Newborn => 0.5,
+            LifeStage::Juvenile => 0.75,
+            LifeStage::Adult => 1.0,
+            LifeStage::Elderly => 0.95,
+        }
+    }
+
+    pub fn speed_factor(&self) -> f32 {
+        match self {
+            LifeStage::Newborn => 0.6,
+            LifeStage::Juvenile => 0.9,
+            LifeStage::Adult => 1.0,
+            LifeStage::Elderly => 0.75,
+        }
+    }
+
+    // Scales how fast the urge to mate builds up, 0 means the animal can't reproduce at all
+    pub fn fertility(&self) -> f32 {
+        match self {
+            LifeStage::Newborn | LifeStage::Juvenile => 0.0,
+            LifeStage::Adult => 1.0,
+            LifeStage::Elderly => 0.3,
+        }
+    }
+
+    // How easy the animal is to catch, scales the distance a predator can kill it from
+    pub fn vulnerability(&self) -> f32 {
+        match self {
+            LifeStage::Newborn => 2.0,
+            LifeStage::Juvenile => 1.3,
+            LifeStage::Adult => 1.0,
+            LifeStage::Elderly => 1.5,
+        }
+    }
+}
+
+// Per species ages in seconds at which each stage starts, and the Gompertz mortality curve.
+// The chance of dying of old age per second is mortality_base * e^(mortality_growth * age).
+#[derive(Component, Clone, Copy, Deserialize)]
+pub struct LifeCycle {
+    pub juvenile_age: f32,
+    pub adult_age: f32,
+    pub elderly_age: f32,
+    pub mortality_base: f32,
+    pub mortality_growth: f32,
+}
+
+impl LifeCycle {
+    pub fn stage_at(&self, age: f32) -> LifeStage {
+        if age >= self.elderly_age {
+            LifeStage::Elderly
+        } else if age >= self.adult_age {
+            LifeStage::Adult
+        } else if age >= self.juvenile_age {
+            LifeStage::Juvenile
+        } else {
+            LifeStage::Newborn
+        }
+    }
+
+    pub fn mortality_rate(&self, age: f32) -> f32 {
+        self.mortality_base * (self.mortality_growth * age).exp()
+    }
+}
+
+impl AgingPlugin {
+    fn grow_older(mut query: Query<&mut Age>, time: Res<Time>) {
+        for mut age in query.iter_mut() {
+            age.0 += time.delta_seconds();
+        }
+    }
+
+    fn update_life_stages(
+        mut query: Query<(
+            Entity,
+            &Age,
+            &LifeCycle,
+            &mut LifeStage,
+            Option<&SelectedAnimal>,
+        )>,
+    ) {
+        for (entity, age, life_cycle, mut life_stage, selected) in query.iter_mut() {
+            let stage = life_cycle.stage_at(age.0);
+            if stage != *life_stage {
+                if selected.is_some() {
+                    println!("{:?} is now {:?}", entity, stage);
+                }
+                *life_stage = stage;
+            }
+        }
+    }
+
+    fn die_of_old_age(
+        query: Query<(Entity, &Age, &LifeCycle)>,
+        mut ev_died: EventWriter<AnimalDiedEvent>,
+        time: Res<Time>,
+    ) {
+        let mut rng = thread_rng();
+        for (entity, age, life_cycle) in query.iter() {
+            let death_chance =
+                1.0 - (-life_cycle.mortality_rate(age.0) * time.delta_seconds()).exp();
+            if rng.gen::<f32>() < death_chance {
+                ev_died.send(AnimalDiedEvent {
+                    entity,
+                    cause: DeathCause::OldAge,
+                });
+            }
+        }
+    }
+
+    fn log_age_structure(
+        query: Query<(&AnimalType, &LifeStage)>,
+        mut timer: ResMut<AgeStructureLogTimer>,
+        species: Res<SpeciesRegistry>,
+        time: Res<Time>,
+    ) {
+        timer.0.tick(time.delta());
+        if !timer.0.just_finished() {
+            return;
+        }
+
+        let mut counts: HashMap<AnimalType, [usize; 4]> = HashMap::new();
+        for (animal_type, life_stage) in query.iter() {
+            counts.entry(*animal_type).or_default()[*life_stage as usize] += 1;
+        }
+        for (animal_type, stage_counts) in counts.iter() {
+            let stages: Vec<String> = LifeStage::iter()
+                .map(|stage| format!("{:?} {}", stage, stage_counts[stage as usize]))
+                .collect();
+            println!(
+                "{} age structure: {}",
+                species.name(*animal_type),
+                stages.join(", ")
+            );
+        }
+    }
+}
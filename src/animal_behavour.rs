@@ -1,12 +1,22 @@
-use bevy::utils::HashMap;
+use bevy::utils::{HashMap, HashSet};
 
 use crate::prelude::*;
 
-// At some point each animal will have its own speed but for now this is good enough
-const BUNNY_SPEED: f32 = 15.0;
 const VELOCITY_REAPPLY_TILE_PROXIMITY: f32 = 6.0;
+const SNEAK_SPEED_FACTOR: f32 = 0.5;
+// Stopping is quicker than getting up to speed
+const BRAKING_FACTOR: f32 = 2.0;
 pub struct AnimalBehaviourPlugin;
 
+// Path following decides where to go, steering makes room for the other animals, and only then
+// the animals actually move
+#[derive(SystemLabel, Clone, Hash, Debug, PartialEq, Eq)]
+pub enum MovementSystem {
+    FollowPath,
+    Steer,
+    Move,
+}
+
 impl Plugin for AnimalBehaviourPlugin {
     fn build(&self, app: &mut App) {
         let mut animal_direction_map: HashMap<AnimalDirection, Vec2> = HashMap::new();
@@ -16,35 +26,119 @@ impl Plugin for AnimalBehaviourPlugin {
         animal_direction_map.insert(AnimalDirection::Right, Vec2::new(1.0, 0.0));
 
         app.add_event::<ApplyVelocityEvent>()
+            .add_event::<AnimalDiedEvent>()
             .insert_resource(AnimalDirectionVectorMap(animal_direction_map))
             .add_system(Self::apply_initial_velocity)
-            .add_system(Self::move_along_path)
+            .add_system(Self::move_along_path.label(MovementSystem::FollowPath))
             .add_system(Self::apply_velocity)
-            .add_system(Self::move_animals)
+            .add_system(Self::move_animals.label(MovementSystem::Move))
             .add_system(Self::evaluate_animal_direction)
-            .add_system(Self::evaluate_animal_state);
+            .add_system(Self::evaluate_animal_state)
+            // After every system of the frame has queued its commands for the animal
+            .add_system_to_stage(CoreStage::PostUpdate, Self::despawn_dead_animals);
     }
 }
 
 #[derive(Component)]
 pub struct Velocity(pub Vec2);
 
+#[derive(Component, Clone, Copy)]
+pub struct MovementStats {
+    // World units per second
+    pub walk_speed: f32,
+    pub run_speed: f32,
+    // World units per second squared
+    pub acceleration: f32,
+    // Radians per second
+    pub turn_rate: f32,
+}
+
+impl MovementStats {
+    pub fn speed(&self, gait: Gait) -> f32 {
+        match gait {
+            Gait::Sneak => self.walk_speed * SNEAK_SPEED_FACTOR,
+            Gait::Walk => self.walk_speed,
+            Gait::Run => self.run_speed,
+        }
+    }
+}
+
+#[derive(Component, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Gait {
+    Sneak,
+    #[default]
+    Walk,
+    Run,
+}
+
+// How the animal is actually moving. Velocity is only the direction it wants to go in, this
+// catches up with it at the rate MovementStats allows.
+#[derive(Component, Default)]
+pub struct Momentum {
+    pub heading: Vec2,
+    pub speed: f32,
+}
+
+impl Momentum {
+    pub fn velocity(&self) -> Vec2 {
+        self.heading * self.speed
+    }
+}
+
 pub struct ApplyVelocityEvent {
     pub entity: Entity,
     pub pos: Vec2,
     pub destination: Vec2,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeathCause {
+    Starvation,
+    Dehydration,
+    Predation,
+    OldAge,
+    Disease,
+}
+
+pub struct AnimalDiedEvent {
+    pub entity: Entity,
+    pub cause: DeathCause,
+}
+
 pub struct AnimalDirectionVectorMap(pub HashMap<AnimalDirection, Vec2>);
 
+impl AnimalDirectionVectorMap {
+    pub fn closest_direction(&self, vector: Vec2) -> AnimalDirection {
+        AnimalDirection::iter()
+            .max_by(|a, b| {
+                let a_dot = vector.dot(*self.0.get(a).unwrap());
+                let b_dot = vector.dot(*self.0.get(b).unwrap());
+                a_dot
+                    .partial_cmp(&b_dot)
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            })
+            .unwrap()
+    }
+}
+
 impl AnimalBehaviourPlugin {
+    #[allow(clippy::type_complexity)]
     fn move_along_path(
-        mut query: Query<(Entity, &Animal, &mut Path, &Pos)>,
+        mut query: Query<(
+            Entity,
+            &Animal,
+            &mut Path,
+            &Pos,
+            &MovementStats,
+            &Momentum,
+            Option<&Locomotion>,
+        )>,
         mut ev_apply_velocity: EventWriter<ApplyVelocityEvent>,
+        pathfinder: Res<Pathfinder>,
         mut commands: Commands,
     ) {
-        for (entity, _, mut path, pos) in query.iter_mut() {
-            if path.0.len() == 0 {
+        for (entity, _, mut path, pos, stats, momentum, locomotion) in query.iter_mut() {
+            if path.0.is_empty() {
                 commands.entity(entity).remove::<Path>();
                 commands.entity(entity).remove::<Velocity>();
                 continue;
@@ -52,13 +146,15 @@ impl AnimalBehaviourPlugin {
 
             let next_step_in_path = path.0[0];
             let distance_to_next = (pos.0 - next_step_in_path).length();
+            // A fast animal that turns slowly needs more room, otherwise it circles the waypoint
+            let proximity = VELOCITY_REAPPLY_TILE_PROXIMITY.max(momentum.speed / stats.turn_rate);
             let velocity_reapply_range_min = Vec2::new(
-                next_step_in_path.x - VELOCITY_REAPPLY_TILE_PROXIMITY,
-                next_step_in_path.y - VELOCITY_REAPPLY_TILE_PROXIMITY,
+                next_step_in_path.x - proximity,
+                next_step_in_path.y - proximity,
             );
             let velocity_reapply_range_max = Vec2::new(
-                next_step_in_path.x + VELOCITY_REAPPLY_TILE_PROXIMITY,
-                next_step_in_path.y + VELOCITY_REAPPLY_TILE_PROXIMITY,
+                next_step_in_path.x + proximity,
+                next_step_in_path.y + proximity,
             );
             println!("disatnce to next{}", distance_to_next);
             if pos.0.x >= velocity_reapply_range_min.x
@@ -68,10 +164,27 @@ impl AnimalBehaviourPlugin {
             {
                 println!("Reapplying velocity");
                 // pos.0 = path.0.pop_front().unwrap();
+                let destination = path.0.pop_front().unwrap();
+
+                // The map could have changed since the path was planned, so make sure the next
+                // leg doesn't cut through anything the animal can't enter
+                if let Some(next_step) = path.0.front() {
+                    let locomotion = locomotion.copied().unwrap_or_default();
+                    if !pathfinder.is_segment_clear(destination, *next_step, locomotion) {
+                        let goal = *path.0.back().unwrap();
+                        commands
+                            .entity(entity)
+                            .remove::<Path>()
+                            .remove::<Velocity>()
+                            .insert(PathRequest::new(goal));
+                        continue;
+                    }
+                }
+
                 ev_apply_velocity.send(ApplyVelocityEvent {
                     entity,
                     pos: pos.0,
-                    destination: path.0.pop_front().unwrap(),
+                    destination,
                 });
             }
         }
@@ -114,8 +227,9 @@ impl AnimalBehaviourPlugin {
             *state = AnimalState::Moving;
         }
 
+        // Eating, drinking and the like are set by their own systems, only stopping is handled here
         for (_, mut state) in idle_animal_query.iter_mut() {
-            if *state == AnimalState::Idle || *state == AnimalState::Eating {
+            if *state != AnimalState::Moving {
                 continue;
             }
             *state = AnimalState::Idle;
@@ -147,10 +261,121 @@ impl AnimalBehaviourPlugin {
         }
     }
 
-    fn move_animals(mut query: Query<(&Animal, &Velocity, &mut Pos)>, time: Res<Time>) {
-        for (_, velocity, mut pos) in query.iter_mut() {
-            println!("Velocity: {} {}", velocity.0.x, velocity.0.y);
-            pos.0 += velocity.0 * BUNNY_SPEED * time.delta_seconds();
+    // An animal can be reported dead more than once on the same frame, say by starving while a
+    // fox catches it. The first report is the cause of death, and the only one that is counted.
+    fn despawn_dead_animals(
+        mut ev_died: EventReader<AnimalDiedEvent>,
+        selected_query: Query<(), With<SelectedAnimal>>,
+        mut disease_stats: ResMut<DiseaseStats>,
+        mut commands: Commands,
+    ) {
+        let mut dead_animals: HashSet<Entity> = HashSet::new();
+        for ev in ev_died.iter() {
+            if dead_animals.insert(ev.entity) {
+                if ev.cause == DeathCause::Disease {
+                    disease_stats.record_death();
+                }
+                if selected_query.contains(ev.entity) {
+                    println!("{:?} died: {:?}", ev.entity, ev.cause);
+                }
+                commands.entity(ev.entity).despawn();
+            }
+        }
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn move_animals(
+        mut query: Query<(
+            Entity,
+            &Animal,
+            &MovementStats,
+            &Gait,
+            &LifeStage,
+            &Infection,
+            &mut Momentum,
+            &mut Pos,
+            Option<&Velocity>,
+            Option<&Steering>,
+            Option<&Locomotion>,
+            Option<&Path>,
+        )>,
+        map: Res<Map>,
+        time: Res<Time>,
+        mut commands: Commands,
+    ) {
+        for (
+            entity,
+            _,
+            stats,
+            gait,
+            life_stage,
+            infection,
+            mut momentum,
+            mut pos,
+            velocity,
+            steering,
+            locomotion,
+            path,
+        ) in query.iter_mut()
+        {
+            let delta = time.delta_seconds();
+            let (target_heading, target_speed) = match velocity {
+                Some(velocity) => {
+                    println!("Velocity: {} {}", velocity.0.x, velocity.0.y);
+                    let speed =
+                        stats.speed(*gait) * life_stage.speed_factor() * infection.speed_factor();
+                    match steering {
+                        Some(steering) => (steering.direction, speed * steering.speed_factor),
+                        None => (velocity.0, speed),
+                    }
+                }
+                // Without a velocity the animal wants to stop, it keeps sliding until it does
+                None => (momentum.heading, 0.0),
+            };
+
+            if momentum.heading == Vec2::ZERO || momentum.speed == 0.0 {
+                momentum.heading = target_heading;
+            } else if target_heading.is_finite() && target_heading != Vec2::ZERO {
+                let max_turn = stats.turn_rate * delta;
+                let turn = momentum
+                    .heading
+                    .angle_between(target_heading)
+                    .clamp(-max_turn, max_turn);
+                momentum.heading = Vec2::from_angle(turn).rotate(momentum.heading);
+            }
+
+            let max_change = if target_speed > momentum.speed {
+                stats.acceleration * delta
+            } else {
+                stats.acceleration * BRAKING_FACTOR * delta
+            };
+            momentum.speed += (target_speed - momentum.speed).clamp(-max_change, max_change);
+            if momentum.speed <= 0.0 {
+                continue;
+            }
+
+            let locomotion = locomotion.copied().unwrap_or_default();
+            let step = momentum.velocity() * delta;
+
+            // Animals move slower through the tiles that are harder for them to traverse and
+            // never step onto a tile they can't enter
+            let tile_cost = map
+                .tile_at(pos.0 + step)
+                .and_then(|tile| locomotion.tile_cost(tile));
+            match tile_cost {
+                Some(tile_cost) => pos.0 += step / tile_cost,
+                None => {
+                    // Pushed off the planned route, plan a new one from here instead of
+                    // walking into the obstacle forever
+                    momentum.speed = 0.0;
+                    let goal = path.and_then(|path| path.0.back().copied());
+                    let mut entity_commands = commands.entity(entity);
+                    entity_commands.remove::<Path>().remove::<Velocity>();
+                    if let Some(goal) = goal {
+                        entity_commands.insert(PathRequest::new(goal));
+                    }
+                }
+            }
 
             // println!("Pos: {} {}", pos.0.x, pos.0.y);
         }
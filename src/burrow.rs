@@ -0,0 +1,222 @@
+use crate::prelude::*;
+
+// Seconds of digging before the burrow is done
+const DIG_TIME: f32 = 4.0;
+// Keeps burrows from being dug right next to each other
+const MIN_BURROW_SPACING: f32 = (TILE_SIZE * 8) as f32;
+// In tiles, how far away an animal looks for a place to dig
+const BURROW_SITE_SEARCH_RADIUS: i32 = 10;
+const ENTER_DISTANCE: f32 = TILE_SIZE as f32;
+const HOME_SEARCH_BUDGET: usize = 1500;
+const BURROW_COLOR: Color = Color::rgb(0.35, 0.2, 0.1);
+
+pub struct BurrowPlugin;
+
+impl Plugin for BurrowPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(Self::seek_shelter)
+            .add_system(Self::dig)
+            .add_system(Self::leave_burrows);
+    }
+}
+
+#[derive(Component)]
+pub struct Burrow;
+
+// The burrow the animal lives in
+#[derive(Component, Clone, Copy)]
+pub struct Home(pub Entity);
+
+// Hidden from everyone outside, and can't see out either
+#[derive(Component)]
+pub struct InBurrow;
+
+#[derive(Component)]
+pub struct Digging(Timer);
+
+impl BurrowPlugin {
+    // Heads home and goes in, animals without a home dig one first. Animals that have a home
+    // also go there to sleep
+    #[allow(clippy::type_complexity)]
+    fn seek_shelter(
+        mut query: Query<
+            (
+                Entity,
+                &mut Pos,
+                &mut Momentum,
+                &Locomotion,
+                &Decision,
+                Option<&Home>,
+                Option<&Alarm>,
+                Option<&mut Visibility>,
+            ),
+            (
+                Without<InBurrow>,
+                Without<Digging>,
+                Without<Burrow>,
+                Without<Path>,
+                Without<PathRequest>,
+                Without<PathfindingTask>,
+            ),
+        >,
+        burrow_query: Query<&Pos, With<Burrow>>,
+        map: Res<Map>,
+        mut commands: Commands,
+    ) {
+        for (entity, mut pos, mut momentum, locomotion, decision, home, alarm, visibility) in
+            query.iter_mut()
+        {
+            let sleeping_at_home = decision.action == Action::Sleep && home.is_some();
+            if decision.action != Action::Shelter && !sleeping_at_home {
+                continue;
+            }
+            let options = PathOptions {
+                max_nodes: Some(HOME_SEARCH_BUDGET),
+                locomotion: *locomotion,
+                ..default()
+            };
+
+            let burrow_pos = home.and_then(|home| burrow_query.get(home.0).ok());
+            if let Some(burrow_pos) = burrow_pos {
+                if (burrow_pos.0 - pos.0).length() <= ENTER_DISTANCE {
+                    pos.0 = burrow_pos.0;
+                    momentum.speed = 0.0;
+                    if let Some(mut visibility) = visibility {
+                        visibility.is_visible = false;
+                    }
+                    commands
+                        .entity(entity)
+                        .remove::<Velocity>()
+                        .insert(InBurrow)
+                        .insert(Gait::Walk)
+                        .insert(AnimalState::Idle);
+                } else {
+                    // Running for it when something is after the animal
+                    let gait = if alarm.is_some() {
+                        Gait::Run
+                    } else {
+                        Gait::Walk
+                    };
+                    commands.entity(entity).insert(gait).insert(PathRequest {
+                        goal: burrow_pos.0,
+                        options,
+                    });
+                }
+                continue;
+            }
+            if decision.action != Action::Shelter {
+                continue;
+            }
+
+            let burrows: Vec<Vec2> = burrow_query.iter().map(|burrow_pos| burrow_pos.0).collect();
+            if is_burrow_site(pos.0, &map, &burrows) {
+                commands
+                    .entity(entity)
+                    .remove::<Velocity>()
+                    .insert(Digging(Timer::from_seconds(DIG_TIME, false)))
+                    .insert(AnimalState::Idle);
+            } else if let Some(site) = find_burrow_site(pos.0, &map, &burrows) {
+                commands.entity(entity).insert(PathRequest {
+                    goal: site,
+                    options,
+                });
+            }
+        }
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn dig(
+        mut query: Query<(
+            Entity,
+            &Pos,
+            &Decision,
+            &mut Digging,
+            Option<&mut HomeRange>,
+            Option<&SelectedAnimal>,
+        )>,
+        time: Res<Time>,
+        mut commands: Commands,
+    ) {
+        for (entity, pos, decision, mut digging, home_range, selected) in query.iter_mut() {
+            if decision.action != Action::Shelter {
+                commands.entity(entity).remove::<Digging>();
+                continue;
+            }
+            digging.0.tick(time.delta());
+            if !digging.0.finished() {
+                continue;
+            }
+
+            let burrow = commands
+                .spawn_bundle(SpriteBundle {
+                    sprite: Sprite {
+                        color: BURROW_COLOR,
+                        custom_size: Some(Vec2::splat(TILE_SIZE as f32 * 0.9)),
+                        ..default()
+                    },
+                    transform: Transform {
+                        translation: Vec3::new(pos.0.x, pos.0.y, 0.0),
+                        ..default()
+                    },
+                    ..default()
+                })
+                .insert_bundle((Burrow, Pos(pos.0)))
+                .id();
+            // The range is now centered on home
+            if let Some(mut home_range) = home_range {
+                home_range.center = pos.0;
+            }
+            if selected.is_some() {
+                println!("{:?} dug a burrow at {}", entity, pos.0);
+            }
+            commands
+                .entity(entity)
+                .remove::<Digging>()
+                .insert(Home(burrow));
+        }
+    }
+
+    fn leave_burrows(
+        mut query: Query<(Entity, &Decision, Option<&mut Visibility>), With<InBurrow>>,
+        mut commands: Commands,
+    ) {
+        for (entity, decision, visibility) in query.iter_mut() {
+            if matches!(decision.action, Action::Shelter | Action::Sleep) {
+                continue;
+            }
+            if let Some(mut visibility) = visibility {
+                visibility.is_visible = true;
+            }
+            commands.entity(entity).remove::<InBurrow>();
+        }
+    }
+}
+
+// Dry land without trees, away from the water and from other burrows
+fn is_burrow_site(pos: Vec2, map: &Map, burrows: &[Vec2]) -> bool {
+    if !map.tile_at(pos).is_some_and(|tile| tile.is_traversable())
+        || map.is_next_to_water(vec2_to_idx(pos))
+    {
+        return false;
+    }
+    burrows
+        .iter()
+        .all(|burrow_pos| (*burrow_pos - pos).length() >= MIN_BURROW_SPACING)
+}
+
+fn find_burrow_site(pos: Vec2, map: &Map, burrows: &[Vec2]) -> Option<Vec2> {
+    let mut closest: Option<(Vec2, f32)> = None;
+    for dy in -BURROW_SITE_SEARCH_RADIUS..=BURROW_SITE_SEARCH_RADIUS {
+        for dx in -BURROW_SITE_SEARCH_RADIUS..=BURROW_SITE_SEARCH_RADIUS {
+            let site = pos + Vec2::new(dx as f32, dy as f32) * TILE_SIZE as f32;
+            if !is_burrow_site(site, map, burrows) {
+                continue;
+            }
+            let distance = (site - pos).length();
+            if closest.is_none_or(|(_, closest_distance)| distance < closest_distance) {
+                closest = Some((site, distance));
+            }
+        }
+    }
+    closest.map(|(site, _)| site)
+}
@@ -1,13 +1,17 @@
+use serde::Deserialize;
+
 use crate::prelude::*;
 
-#[derive(Component, Hash, PartialEq, Eq)]
+#[derive(Component, Hash, PartialEq, Eq, Clone, Copy, Debug, EnumIter, Deserialize)]
 pub enum AnimalState {
     Idle,
     Moving,
     Eating,
+    Drinking,
+    Sleeping,
 }
 
-#[derive(Component, Hash, PartialEq, Eq, EnumIter, Clone)]
+#[derive(Component, Hash, PartialEq, Eq, EnumIter, Clone, Debug, Deserialize)]
 pub enum AnimalDirection {
     Up,
     Down,
@@ -24,9 +28,42 @@ pub struct RelativeTextureIndex(pub usize);
 
 #[derive(Component)]
 pub struct Animal;
+// Seconds since birth
+#[derive(Component, Default)]
+pub struct Age(pub f32);
 #[derive(Component)]
-pub enum AnimalType {
-    Bunny,
+pub struct Path(pub VecDeque<Vec2>);
+#[derive(Component, Clone, Copy)]
+pub struct PathRequest {
+    pub goal: Vec2,
+    pub options: PathOptions,
+}
+
+impl PathRequest {
+    pub fn new(goal: Vec2) -> Self {
+        PathRequest {
+            goal,
+            options: PathOptions::default(),
+        }
+    }
+}
+// Path to whichever of the goals is the cheapest to walk to, max_cost is in tiles of walking
+#[derive(Component, Clone)]
+pub struct NearestPathRequest {
+    pub goals: Vec<Vec2>,
+    pub max_cost: f32,
+    pub options: PathOptions,
 }
+// Where the path the animal got last ends, which is the tile it picked for a NearestPathRequest
 #[derive(Component)]
-pub struct Path(pub VecDeque<Vec2>);
+pub struct PathOutcome {
+    pub end: Vec2,
+}
+// Goals to try in turn when no path to the requested one is found, best first
+#[derive(Component)]
+pub struct FallbackGoals(pub VecDeque<Vec2>);
+#[derive(Component)]
+pub struct PathFailed {
+    pub goal: Vec2,
+    pub error: PathError,
+}
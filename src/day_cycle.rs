@@ -0,0 +1,80 @@
+use serde::Deserialize;
+
+use crate::prelude::*;
+
+// In seconds
+const DAY_LENGTH: f32 = 120.0;
+// The last part of every day is night
+const NIGHT_FRACTION: f32 = 0.35;
+// Share of the day on either side of dawn and dusk
+const TWILIGHT_FRACTION: f32 = 0.08;
+
+pub struct DayCyclePlugin;
+
+impl Plugin for DayCyclePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(DayCycle::default())
+            .add_system(Self::advance_time);
+    }
+}
+
+#[derive(Default)]
+pub struct DayCycle {
+    elapsed: f32,
+}
+
+impl DayCycle {
+    pub fn day(&self) -> u32 {
+        (self.elapsed / DAY_LENGTH) as u32 + 1
+    }
+
+    // 0 at dawn, goes up to 1 at the end of the night
+    pub fn time_of_day(&self) -> f32 {
+        (self.elapsed / DAY_LENGTH).fract()
+    }
+
+    pub fn is_night(&self) -> bool {
+        self.time_of_day() >= 1.0 - NIGHT_FRACTION
+    }
+
+    // Around dawn and dusk
+    pub fn is_twilight(&self) -> bool {
+        let time_of_day = self.time_of_day();
+        let from_dawn = time_of_day.min(1.0 - time_of_day);
+        let from_dusk = (time_of_day - (1.0 - NIGHT_FRACTION)).abs();
+        from_dawn <= TWILIGHT_FRACTION || from_dusk <= TWILIGHT_FRACTION
+    }
+}
+
+// When in the day a species is up and about, it sleeps the rest of the time
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize)]
+pub enum ActivityPattern {
+    Diurnal,
+    Nocturnal,
+    // Out at dawn and dusk
+    Crepuscular,
+}
+
+impl ActivityPattern {
+    pub fn is_active(&self, day_cycle: &DayCycle) -> bool {
+        match self {
+            ActivityPattern::Diurnal => !day_cycle.is_night(),
+            ActivityPattern::Nocturnal => day_cycle.is_night(),
+            ActivityPattern::Crepuscular => day_cycle.is_twilight(),
+        }
+    }
+}
+
+impl DayCyclePlugin {
+    fn advance_time(mut day_cycle: ResMut<DayCycle>, time: Res<Time>) {
+        let was_night = day_cycle.is_night();
+        let day = day_cycle.day();
+        day_cycle.elapsed += time.delta_seconds();
+
+        if day_cycle.day() != day {
+            println!("Day {} begins", day_cycle.day());
+        } else if day_cycle.is_night() && !was_night {
+            println!("Night falls on day {}", day_cycle.day());
+        }
+    }
+}
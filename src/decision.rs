@@ -0,0 +1,349 @@
+use std::time::Duration;
+
+use bevy::utils::HashMap;
+
+use crate::prelude::*;
+
+const DECISION_INTERVAL: f32 = 0.5;
+// A new action has to beat the current one by this much, otherwise animals keep flip-flopping
+const COMMITMENT_BONUS: f32 = 0.1;
+// Don't retry an action that just failed to find a path for a while
+const ACTION_FAILURE_COOLDOWN: f64 = 5.0;
+const WANDER_UTILITY: f32 = 0.15;
+const REST_UTILITY: f32 = 0.1;
+// Staying alive beats any need
+const FLEE_UTILITY: f32 = 1.5;
+// Keeps mating below urgent needs
+const MATE_WEIGHT: f32 = 0.7;
+// Diving into a burrow beats running, as long as it's close enough to make it there
+const SHELTER_FROM_DANGER_UTILITY: f32 = 1.6;
+const SAFE_HOME_DISTANCE: f32 = (TILE_SIZE * 12) as f32;
+// Mothers head home to give birth
+const NESTING_UTILITY: f32 = 0.6;
+// Seconds before the birth
+const NESTING_TIME: f32 = 10.0;
+const NIGHT_SHELTER_UTILITY: f32 = 0.3;
+// Enough to keep animals asleep through their rest time once they've caught up on sleep
+const REST_TIME_SLEEP_UTILITY: f32 = 0.25;
+
+pub struct DecisionPlugin;
+
+impl Plugin for DecisionPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(Self::decide_actions);
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, EnumIter)]
+pub enum Action {
+    Drink,
+    Eat,
+    Hunt,
+    Wander,
+    Flee,
+    Mate,
+    Rest,
+    Shelter,
+    Sleep,
+}
+
+#[derive(Component)]
+pub struct Decision {
+    pub action: Action,
+    // Scores of the last evaluation, highest first. This is the reasoning behind the action
+    pub scores: Vec<(Action, f32)>,
+    timer: Timer,
+    failed_at: HashMap<Action, f64>,
+}
+
+impl Default for Decision {
+    fn default() -> Self {
+        let mut timer = Timer::from_seconds(DECISION_INTERVAL, true);
+        // Stagger the evaluations so that not every animal thinks on the same frame
+        timer.set_elapsed(Duration::from_secs_f32(
+            thread_rng().gen_range(0.0..DECISION_INTERVAL),
+        ));
+        Decision {
+            action: Action::Rest,
+            scores: vec![],
+            timer,
+            failed_at: HashMap::new(),
+        }
+    }
+}
+
+impl Decision {
+    pub fn score_of(&self, action: Action) -> f32 {
+        self.scores
+            .iter()
+            .find(|(scored_action, _)| *scored_action == action)
+            .map_or(0.0, |(_, score)| *score)
+    }
+
+    pub fn describe(&self) -> String {
+        let scores: Vec<String> = self
+            .scores
+            .iter()
+            .map(|(action, score)| format!("{:?} {:.2}", action, score))
+            .collect();
+        format!("{:?} <- [{}]", self.action, scores.join(", "))
+    }
+}
+
+// Marks the animal whose reasoning gets printed
+#[derive(Component)]
+pub struct SelectedAnimal;
+
+struct DecisionContext {
+    diet: Diet,
+    hunger: f32,
+    thirst: f32,
+    fatigue: f32,
+    // Plants for herbivores, prey or carcasses for carnivores
+    food_visible: bool,
+    water_visible: bool,
+    // Confidence in the best remembered place, 0 when nothing is remembered
+    remembered_food: f32,
+    remembered_water: f32,
+    // 0 unless the animal has found a partner
+    mating_urge: f32,
+    // A predator has been seen recently
+    threatened: bool,
+    has_home: bool,
+    home_nearby: bool,
+    // Has no home yet but is able to dig one
+    can_dig: bool,
+    nesting: bool,
+    night: bool,
+    // Outside the hours the species is active in
+    rest_time: bool,
+}
+
+// Quadratic response, needs barely matter when low and dominate once they get close to the limit
+fn need_curve(need: f32) -> f32 {
+    (need / NEED_LIMIT).clamp(0.0, 1.0).powi(2)
+}
+
+fn score_action(action: Action, context: &DecisionContext) -> f32 {
+    match action {
+        Action::Drink if context.water_visible => need_curve(context.thirst),
+        Action::Eat if context.food_visible && context.diet == Diet::Herbivore => {
+            need_curve(context.hunger)
+        }
+        Action::Hunt if context.food_visible && context.diet == Diet::Carnivore => {
+            need_curve(context.hunger)
+        }
+        // Going back to a remembered place beats searching blindly, as long as the animal is
+        // sure enough that it's still worth it
+        Action::Drink => need_curve(context.thirst) * context.remembered_water,
+        Action::Eat if context.diet == Diet::Herbivore => {
+            need_curve(context.hunger) * context.remembered_food
+        }
+        Action::Hunt if context.diet == Diet::Carnivore => {
+            need_curve(context.hunger) * context.remembered_food
+        }
+        // Wandering around is the only way to find what hasn't been seen yet
+        Action::Eat | Action::Hunt => 0.0,
+        Action::Wander => WANDER_UTILITY,
+        Action::Flee if context.threatened => FLEE_UTILITY,
+        Action::Flee => 0.0,
+        Action::Mate => MATE_WEIGHT * context.mating_urge,
+        Action::Rest => REST_UTILITY,
+        Action::Shelter if context.threatened && context.home_nearby => SHELTER_FROM_DANGER_UTILITY,
+        Action::Shelter if context.nesting => NESTING_UTILITY,
+        Action::Shelter if context.night && (context.has_home || context.can_dig) => {
+            NIGHT_SHELTER_UTILITY
+        }
+        Action::Shelter => 0.0,
+        // Nobody dozes off with a predator around
+        Action::Sleep if context.threatened => 0.0,
+        Action::Sleep if context.rest_time => need_curve(context.fatigue) + REST_TIME_SLEEP_UTILITY,
+        Action::Sleep => need_curve(context.fatigue),
+    }
+}
+
+impl DecisionPlugin {
+    #[allow(clippy::type_complexity)]
+    fn decide_actions(
+        mut query: Query<
+            (
+                Entity,
+                (
+                    &LifeStage,
+                    &Memory,
+                    &Fatigue,
+                    Option<&Home>,
+                    Option<&Pregnancy>,
+                ),
+                &AnimalType,
+                &Diet,
+                &Pos,
+                &Hunger,
+                &Thirst,
+                &Perceived,
+                &mut Decision,
+                &mut AnimalState,
+                Option<&PathFailed>,
+                Option<&SelectedAnimal>,
+                Option<&ReproductiveUrge>,
+                Option<&Mate>,
+                Option<ChangeTrackers<Alarm>>,
+            ),
+            With<Animal>,
+        >,
+        burrow_query: Query<&Pos, With<Burrow>>,
+        map: Res<Map>,
+        day_cycle: Res<DayCycle>,
+        species: Res<SpeciesRegistry>,
+        time: Res<Time>,
+        mut commands: Commands,
+    ) {
+        let now = time.seconds_since_startup();
+
+        for (
+            entity,
+            (life_stage, memory, fatigue, home, pregnancy),
+            animal_type,
+            diet,
+            pos,
+            hunger,
+            thirst,
+            perceived,
+            mut decision,
+            mut state,
+            path_failed,
+            selected,
+            urge,
+            mate,
+            alarm,
+        ) in query.iter_mut()
+        {
+            let definition = species.get(*animal_type);
+            // Spotting a predator can't wait for the next evaluation
+            let mut force_decision = alarm.as_ref().is_some_and(|alarm| alarm.is_added());
+            if let Some(path_failed) = path_failed {
+                if selected.is_some() {
+                    println!(
+                        "{:?} couldn't find a path to {} for {:?}: {:?}",
+                        entity, path_failed.goal, decision.action, path_failed.error
+                    );
+                }
+                let action = decision.action;
+                decision.failed_at.insert(action, now);
+                commands.entity(entity).remove::<PathFailed>();
+                force_decision = true;
+            }
+
+            decision.timer.tick(time.delta());
+            if !decision.timer.just_finished() && !force_decision {
+                continue;
+            }
+
+            let food_visible = match diet {
+                Diet::Herbivore => !perceived.plants.is_empty(),
+                Diet::Carnivore => {
+                    !perceived.carcasses.is_empty()
+                        || perceived
+                            .animals
+                            .iter()
+                            .any(|other| definition.hunts(other.animal_type))
+                }
+            };
+            let home_pos = home.and_then(|home| burrow_query.get(home.0).ok());
+            let remembered = |kind: MemoryKind| {
+                memory
+                    .best(kind, pos.0)
+                    .map_or(0.0, |remembered| remembered.confidence)
+            };
+            let context = DecisionContext {
+                diet: *diet,
+                hunger: hunger.0,
+                thirst: thirst.0,
+                fatigue: fatigue.0,
+                food_visible,
+                // Already standing at the shore counts as seeing water
+                water_visible: !perceived.water.is_empty()
+                    || (map.in_bounds(pos.0) && map.is_next_to_water(vec2_to_idx(pos.0))),
+                remembered_food: remembered(MemoryKind::Food),
+                remembered_water: remembered(MemoryKind::Water),
+                mating_urge: match (urge, mate) {
+                    (Some(urge), Some(_)) => urge.0,
+                    _ => 0.0,
+                },
+                threatened: alarm.is_some(),
+                has_home: home_pos.is_some(),
+                home_nearby: home_pos
+                    .is_some_and(|home_pos| (home_pos.0 - pos.0).length() <= SAFE_HOME_DISTANCE),
+                can_dig: home_pos.is_none()
+                    && definition.digs_burrows
+                    && matches!(life_stage, LifeStage::Adult | LifeStage::Elderly),
+                nesting: home_pos.is_some()
+                    && pregnancy.is_some_and(|pregnancy| {
+                        pregnancy.timer.duration().as_secs_f32() - pregnancy.timer.elapsed_secs()
+                            <= NESTING_TIME
+                    }),
+                night: day_cycle.is_night(),
+                rest_time: !definition.activity_pattern.is_active(&day_cycle),
+            };
+            let mut scores: Vec<(Action, f32)> = Action::iter()
+                .map(|action| {
+                    let recently_failed = decision
+                        .failed_at
+                        .get(&action)
+                        .is_some_and(|failed_at| now - failed_at < ACTION_FAILURE_COOLDOWN);
+                    if recently_failed {
+                        (action, 0.0)
+                    } else {
+                        (action, score_action(action, &context))
+                    }
+                })
+                .collect();
+            scores.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+            decision.scores = scores;
+
+            let (best_action, best_score) = decision.scores[0];
+            let current_score = decision.score_of(decision.action);
+            let switch_action = best_action != decision.action
+                && (best_score > current_score + COMMITMENT_BONUS || force_decision);
+
+            if switch_action {
+                decision.action = best_action;
+                commands
+                    .entity(entity)
+                    .remove::<Path>()
+                    .remove::<PathRequest>()
+                    .remove::<NearestPathRequest>()
+                    .remove::<FallbackGoals>()
+                    .remove::<PathfindingTask>()
+                    .remove::<PathOutcome>()
+                    .remove::<RememberedGoal>()
+                    .remove::<Velocity>();
+            } else if !force_decision {
+                continue;
+            }
+
+            if selected.is_some() {
+                println!("{:?}: {}", entity, decision.describe());
+            }
+
+            match decision.action {
+                // Picking where to go is up to WanderPlugin, DrinkingPlugin, EatingPlugin,
+                // HuntingPlugin, FleePlugin, BurrowPlugin and SleepPlugin
+                Action::Wander
+                | Action::Drink
+                | Action::Eat
+                | Action::Hunt
+                | Action::Flee
+                | Action::Shelter
+                | Action::Sleep => {}
+                Action::Rest | Action::Mate => {
+                    commands
+                        .entity(entity)
+                        .remove::<Path>()
+                        .remove::<Velocity>();
+                    *state = AnimalState::Idle;
+                }
+            }
+        }
+    }
+}
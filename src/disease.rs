@@ -0,0 +1,509 @@
+use std::time::Duration;
+
+use bevy::utils::HashMap;
+
+use crate::prelude::*;
+
+// Close enough to pass the pathogen on
+const CONTACT_RADIUS: f32 = (TILE_SIZE * 2) as f32;
+// Chance per second of infecting an animal that stays in contact
+const CONTACT_TRANSMISSION_PROBABILITY: f32 = 0.2;
+// Chance per second of catching it while drinking from contaminated water
+const WATER_TRANSMISSION_PROBABILITY: f32 = 0.1;
+// Seconds the pathogen survives in water a sick animal drank from
+const WATER_CONTAMINATION_TIME: f64 = 40.0;
+// Seconds an animal stays sick and infectious
+const MIN_INFECTIOUS_PERIOD: f32 = 20.0;
+const MAX_INFECTIOUS_PERIOD: f32 = 40.0;
+// Chance that the infection kills the animal instead of leaving it immune
+const CASE_FATALITY: f32 = 0.25;
+const INFECTED_SPEED_FACTOR: f32 = 0.7;
+// Per second, multiplied by mass, on top of the normal metabolism
+const INFECTION_ENERGY_COST: f32 = 0.4;
+// Chance per second of the pathogen jumping over from outside, only while there's no outbreak
+const SPILLOVER_PROBABILITY: f32 = 1.0 / 240.0;
+const DISEASE_LOG_INTERVAL: f32 = 30.0;
+
+pub struct DiseasePlugin;
+
+impl Plugin for DiseasePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(DiseaseStats::default())
+            .insert_resource(ContaminatedWater::default())
+            .insert_resource(DiseaseLogTimer(Timer::from_seconds(
+                DISEASE_LOG_INTERVAL,
+                true,
+            )))
+            .add_system(Self::spill_over)
+            .add_system(Self::spread_by_contact)
+            .add_system(Self::spread_through_water)
+            .add_system(Self::progress_infections)
+            .add_system(Self::track_outbreaks)
+            .add_system(Self::log_disease);
+    }
+}
+
+struct DiseaseLogTimer(Timer);
+
+// Water tiles a sick animal drank from, and until when the pathogen survives in them
+#[derive(Default)]
+struct ContaminatedWater(HashMap<usize, f64>);
+
+impl ContaminatedWater {
+    fn contaminate(&mut self, tile: usize, now: f64) {
+        self.0.insert(tile, now + WATER_CONTAMINATION_TIME);
+    }
+
+    // The pathogen dies off in water no sick animal has drunk from for a while
+    fn expire(&mut self, now: f64) {
+        self.0.retain(|_, until| *until > now);
+    }
+
+    fn is_contaminated(&self, tile: usize) -> bool {
+        self.0.contains_key(&tile)
+    }
+}
+
+// Where the animal is in the SIR model
+#[derive(Component, Default)]
+pub enum Infection {
+    #[default]
+    Susceptible,
+    // Sick and infectious until the timer runs out
+    Infected(Timer),
+    // Immune for the rest of its life
+    Recovered,
+    // Didn't make it, the animal is about to be despawned
+    Fatal,
+}
+
+// How an infection that ran its course ended
+#[derive(Debug, PartialEq, Eq)]
+enum InfectionOutcome {
+    Recovered,
+    Died,
+}
+
+impl Infection {
+    fn new_case() -> Self {
+        let period = thread_rng().gen_range(MIN_INFECTIOUS_PERIOD..MAX_INFECTIOUS_PERIOD);
+        Infection::Infected(Timer::from_seconds(period, false))
+    }
+
+    pub fn is_susceptible(&self) -> bool {
+        matches!(self, Infection::Susceptible)
+    }
+
+    pub fn is_infected(&self) -> bool {
+        matches!(self, Infection::Infected(_))
+    }
+
+    // The pathogen only infects its host species, a fox chasing a sick bunny doesn't catch it
+    fn can_catch_from(&self, animal_type: AnimalType, source_type: AnimalType) -> bool {
+        self.is_susceptible() && animal_type == source_type
+    }
+
+    // Moves the animal on once its infectious period is over, the roll decides whether it
+    // survives. Nothing happens to animals that aren't sick.
+    fn progress(&mut self, delta: Duration, roll: f32) -> Option<InfectionOutcome> {
+        let timer = match self {
+            Infection::Infected(timer) => timer,
+            _ => return None,
+        };
+        timer.tick(delta);
+        if !timer.finished() {
+            return None;
+        }
+        if roll < CASE_FATALITY {
+            *self = Infection::Fatal;
+            Some(InfectionOutcome::Died)
+        } else {
+            *self = Infection::Recovered;
+            Some(InfectionOutcome::Recovered)
+        }
+    }
+
+    pub fn speed_factor(&self) -> f32 {
+        if self.is_infected() {
+            INFECTED_SPEED_FACTOR
+        } else {
+            1.0
+        }
+    }
+
+    // Per second and unit of body mass
+    pub fn energy_cost(&self) -> f32 {
+        if self.is_infected() {
+            INFECTION_ENERGY_COST
+        } else {
+            0.0
+        }
+    }
+}
+
+// Running totals since the start, plus the outbreak that is going on right now
+#[derive(Default)]
+pub struct DiseaseStats {
+    pub outbreaks: usize,
+    pub infections: usize,
+    pub recoveries: usize,
+    pub deaths: usize,
+    outbreak: Option<Outbreak>,
+}
+
+struct Outbreak {
+    started_at: f64,
+    infections: usize,
+    deaths: usize,
+    peak_infected: usize,
+}
+
+impl DiseaseStats {
+    fn start_outbreak(&mut self, now: f64) {
+        self.outbreaks += 1;
+        self.outbreak = Some(Outbreak {
+            started_at: now,
+            infections: 0,
+            deaths: 0,
+            peak_infected: 0,
+        });
+        self.record_infection();
+    }
+
+    fn record_infection(&mut self) {
+        self.infections += 1;
+        if let Some(outbreak) = self.outbreak.as_mut() {
+            outbreak.infections += 1;
+        }
+    }
+
+    fn record_recovery(&mut self) {
+        self.recoveries += 1;
+    }
+
+    pub fn record_death(&mut self) {
+        self.deaths += 1;
+        if let Some(outbreak) = self.outbreak.as_mut() {
+            outbreak.deaths += 1;
+        }
+    }
+
+    // Returns the outbreak once it's over, which is when nobody is sick anymore. Animals it killed
+    // are still waiting to be despawned on that frame, and their deaths belong in the summary, so
+    // it waits for them too.
+    fn end_outbreak_if_over(&mut self, infected: usize, dying: usize) -> Option<Outbreak> {
+        let outbreak = self.outbreak.as_mut()?;
+        outbreak.peak_infected = outbreak.peak_infected.max(infected);
+        if infected + dying > 0 {
+            return None;
+        }
+        self.outbreak.take()
+    }
+}
+
+// Turns a chance per second into the chance for this frame
+fn chance_this_frame(per_second: f32, delta: f32) -> f32 {
+    1.0 - (1.0 - per_second).powf(delta)
+}
+
+impl DiseasePlugin {
+    // Every outbreak starts with one animal catching the pathogen from somewhere outside the map
+    fn spill_over(
+        mut query: Query<(Entity, &AnimalType, &mut Infection)>,
+        mut stats: ResMut<DiseaseStats>,
+        species: Res<SpeciesRegistry>,
+        time: Res<Time>,
+    ) {
+        if stats.outbreak.is_some() {
+            return;
+        }
+        let mut rng = thread_rng();
+        if rng.gen::<f32>() >= chance_this_frame(SPILLOVER_PROBABILITY, time.delta_seconds()) {
+            return;
+        }
+
+        let susceptible: Vec<Entity> = query
+            .iter()
+            .filter(|(_, _, infection)| infection.is_susceptible())
+            .map(|(entity, _, _)| entity)
+            .collect();
+        if susceptible.is_empty() {
+            return;
+        }
+        let patient_zero = susceptible[rng.gen_range(0..susceptible.len())];
+        if let Ok((_, animal_type, mut infection)) = query.get_mut(patient_zero) {
+            *infection = Infection::new_case();
+            stats.start_outbreak(time.seconds_since_startup());
+            println!(
+                "Outbreak {} starts with {:?} ({})",
+                stats.outbreaks,
+                patient_zero,
+                species.name(*animal_type)
+            );
+        }
+    }
+
+    fn spread_by_contact(
+        mut query: Query<(&Pos, &AnimalType, &mut Infection), Without<InBurrow>>,
+        grid: Res<SpatialGrid>,
+        mut stats: ResMut<DiseaseStats>,
+        time: Res<Time>,
+    ) {
+        let chance = chance_this_frame(CONTACT_TRANSMISSION_PROBABILITY, time.delta_seconds());
+        let infected: Vec<(Vec2, AnimalType)> = query
+            .iter()
+            .filter(|(_, _, infection)| infection.is_infected())
+            .map(|(pos, animal_type, _)| (pos.0, *animal_type))
+            .collect();
+
+        let mut rng = thread_rng();
+        for (pos, animal_type) in infected {
+            for (other, _) in grid.nearby(pos, CONTACT_RADIUS) {
+                if let Ok((_, other_type, mut infection)) = query.get_mut(other) {
+                    if !infection.can_catch_from(*other_type, animal_type)
+                        || rng.gen::<f32>() >= chance
+                    {
+                        continue;
+                    }
+                    *infection = Infection::new_case();
+                    stats.record_infection();
+                }
+            }
+        }
+    }
+
+    // Sick animals leave the pathogen in the water they drink from, whoever drinks there next
+    // can pick it up
+    fn spread_through_water(
+        mut query: Query<(&ShoreSpot, &AnimalState, &mut Infection)>,
+        mut contaminated: ResMut<ContaminatedWater>,
+        map: Res<Map>,
+        mut stats: ResMut<DiseaseStats>,
+        time: Res<Time>,
+    ) {
+        let now = time.seconds_since_startup();
+        contaminated.expire(now);
+
+        for (spot, state, infection) in query.iter() {
+            if *state != AnimalState::Drinking || !infection.is_infected() {
+                continue;
+            }
+            if let Some(water_pos) = adjacent_water(spot.0, &map) {
+                contaminated.contaminate(vec2_to_idx(water_pos), now);
+            }
+        }
+
+        let chance = chance_this_frame(WATER_TRANSMISSION_PROBABILITY, time.delta_seconds());
+        let mut rng = thread_rng();
+        for (spot, state, mut infection) in query.iter_mut() {
+            if *state != AnimalState::Drinking || !infection.is_susceptible() {
+                continue;
+            }
+            let drinks_contaminated = adjacent_water(spot.0, &map)
+                .is_some_and(|water_pos| contaminated.is_contaminated(vec2_to_idx(water_pos)));
+            if drinks_contaminated && rng.gen::<f32>() < chance {
+                *infection = Infection::new_case();
+                stats.record_infection();
+            }
+        }
+    }
+
+    fn progress_infections(
+        mut query: Query<(Entity, &mut Infection)>,
+        mut ev_died: EventWriter<AnimalDiedEvent>,
+        mut stats: ResMut<DiseaseStats>,
+        time: Res<Time>,
+    ) {
+        for (entity, mut infection) in query.iter_mut() {
+            // Out of Infected right away either way, so the death is only rolled for once. It's
+            // counted when the animal is despawned, unless something else killed it first.
+            match infection.progress(time.delta(), thread_rng().gen::<f32>()) {
+                Some(InfectionOutcome::Died) => ev_died.send(AnimalDiedEvent {
+                    entity,
+                    cause: DeathCause::Disease,
+                }),
+                Some(InfectionOutcome::Recovered) => stats.record_recovery(),
+                None => {}
+            }
+        }
+    }
+
+    // Sums the outbreak up once it's over
+    fn track_outbreaks(query: Query<&Infection>, mut stats: ResMut<DiseaseStats>, time: Res<Time>) {
+        let infected = query
+            .iter()
+            .filter(|infection| infection.is_infected())
+            .count();
+        let dying = query
+            .iter()
+            .filter(|infection| matches!(infection, Infection::Fatal))
+            .count();
+        let outbreak = match stats.end_outbreak_if_over(infected, dying) {
+            Some(outbreak) => outbreak,
+            None => return,
+        };
+
+        println!(
+            "Outbreak {} is over after {:.0}s: {} infected, {} died, at most {} sick at once",
+            stats.outbreaks,
+            time.seconds_since_startup() - outbreak.started_at,
+            outbreak.infections,
+            outbreak.deaths,
+            outbreak.peak_infected
+        );
+    }
+
+    fn log_disease(
+        query: Query<(&AnimalType, &Infection)>,
+        stats: Res<DiseaseStats>,
+        mut timer: ResMut<DiseaseLogTimer>,
+        species: Res<SpeciesRegistry>,
+        time: Res<Time>,
+    ) {
+        timer.0.tick(time.delta());
+        if !timer.0.just_finished() {
+            return;
+        }
+
+        let mut counts: HashMap<AnimalType, [usize; 3]> = HashMap::new();
+        for (animal_type, infection) in query.iter() {
+            let compartment = match infection {
+                Infection::Susceptible => 0,
+                Infection::Infected(_) => 1,
+                Infection::Recovered => 2,
+                Infection::Fatal => continue,
+            };
+            counts.entry(*animal_type).or_default()[compartment] += 1;
+        }
+        for (animal_type, [susceptible, infected, recovered]) in counts.iter() {
+            println!(
+                "{} disease: susceptible {}, infected {}, recovered {}",
+                species.name(*animal_type),
+                susceptible,
+                infected,
+                recovered
+            );
+        }
+        println!(
+            "Disease totals: {} outbreaks, {} infections, {} recoveries, {} deaths",
+            stats.outbreaks, stats.infections, stats.recoveries, stats.deaths
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chance_over_a_whole_second_is_the_chance_per_second() {
+        assert!((chance_this_frame(0.2, 1.0) - 0.2).abs() < 1e-6);
+    }
+
+    #[test]
+    fn chance_adds_up_the_same_over_frames_of_any_length() {
+        // Not being infected in any of 60 frames is as likely as not being infected in one second
+        let per_frame = chance_this_frame(0.2, 1.0 / 60.0);
+        let over_a_second = 1.0 - (1.0 - per_frame).powi(60);
+        assert!((over_a_second - 0.2).abs() < 1e-4);
+    }
+
+    #[test]
+    fn no_time_no_chance() {
+        assert_eq!(chance_this_frame(0.2, 0.0), 0.0);
+    }
+
+    fn infected_for(seconds: f32) -> Infection {
+        Infection::Infected(Timer::from_seconds(seconds, false))
+    }
+
+    #[test]
+    fn infection_runs_its_course_before_it_ends() {
+        let mut infection = infected_for(10.0);
+        assert_eq!(infection.progress(Duration::from_secs(9), 0.0), None);
+        assert!(infection.is_infected());
+        assert_eq!(
+            infection.progress(Duration::from_secs(1), 0.0),
+            Some(InfectionOutcome::Died)
+        );
+        assert!(matches!(infection, Infection::Fatal));
+    }
+
+    #[test]
+    fn infection_ends_in_immunity_when_the_roll_survives() {
+        let mut infection = infected_for(10.0);
+        assert_eq!(
+            infection.progress(Duration::from_secs(10), CASE_FATALITY),
+            Some(InfectionOutcome::Recovered)
+        );
+        assert!(matches!(infection, Infection::Recovered));
+        assert!(!infection.is_susceptible());
+    }
+
+    #[test]
+    fn only_sick_animals_progress() {
+        for mut infection in [
+            Infection::Susceptible,
+            Infection::Recovered,
+            Infection::Fatal,
+        ] {
+            assert_eq!(infection.progress(Duration::from_secs(100), 0.0), None);
+        }
+    }
+
+    #[test]
+    fn only_susceptible_animals_of_the_same_species_catch_it() {
+        let species = SpeciesRegistry::load("species").unwrap();
+        let mut animal_types = species.all();
+        let (host, other) = (animal_types.next().unwrap(), animal_types.next().unwrap());
+
+        assert!(Infection::Susceptible.can_catch_from(host, host));
+        assert!(!Infection::Susceptible.can_catch_from(other, host));
+        assert!(!Infection::Recovered.can_catch_from(host, host));
+        assert!(!infected_for(10.0).can_catch_from(host, host));
+    }
+
+    #[test]
+    fn contaminated_water_clears_up() {
+        let mut water = ContaminatedWater::default();
+        water.contaminate(7, 100.0);
+        water.expire(100.0 + WATER_CONTAMINATION_TIME - 1.0);
+        assert!(water.is_contaminated(7));
+        assert!(!water.is_contaminated(8));
+        water.expire(100.0 + WATER_CONTAMINATION_TIME);
+        assert!(!water.is_contaminated(7));
+    }
+
+    #[test]
+    fn outbreak_is_tracked_from_start_to_end() {
+        let mut stats = DiseaseStats::default();
+        stats.start_outbreak(5.0);
+        stats.record_infection();
+        stats.record_infection();
+        assert!(stats.end_outbreak_if_over(3, 0).is_none());
+        stats.record_recovery();
+        stats.record_death();
+        // The one it killed hasn't been despawned yet
+        assert!(stats.end_outbreak_if_over(0, 1).is_none());
+
+        let outbreak = stats.end_outbreak_if_over(0, 0).unwrap();
+        assert_eq!(outbreak.started_at, 5.0);
+        assert_eq!(outbreak.infections, 3);
+        assert_eq!(outbreak.deaths, 1);
+        assert_eq!(outbreak.peak_infected, 3);
+        assert!(stats.outbreak.is_none());
+
+        assert_eq!(stats.outbreaks, 1);
+        assert_eq!(stats.infections, 3);
+        assert_eq!(stats.recoveries, 1);
+        assert_eq!(stats.deaths, 1);
+    }
+
+    #[test]
+    fn deaths_outside_an_outbreak_only_count_towards_the_totals() {
+        let mut stats = DiseaseStats::default();
+        stats.record_death();
+        assert_eq!(stats.deaths, 1);
+        assert!(stats.end_outbreak_if_over(0, 0).is_none());
+    }
+}
@@ -0,0 +1,264 @@
+use bevy::utils::{HashMap, HashSet};
+
+use crate::prelude::*;
+
+// Thirst per second
+const DRINK_RATE: f32 = 20.0;
+// Not worth walking to the shore for less than this
+const MIN_THIRST_TO_DRINK: f32 = 5.0;
+// In tiles of walking cost
+const SHORE_SEARCH_RADIUS: f32 = 60.0;
+const SHORE_ARRIVAL_DISTANCE: f32 = TILE_SIZE as f32 * 1.5;
+const REMEMBERED_WATER_SEARCH_BUDGET: usize = 2000;
+
+pub struct DrinkingPlugin;
+
+impl Plugin for DrinkingPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(ShoreReservations::default())
+            .add_system(Self::release_shore_spots)
+            .add_system(Self::seek_shore_spot)
+            // Otherwise the release would drop the reservation before the ShoreSpot is in place
+            .add_system(Self::claim_shore_spot.after(Self::release_shore_spots))
+            .add_system(Self::start_drinking)
+            .add_system(Self::drink);
+    }
+}
+
+// Which animal has claimed which shore tile, so that they don't all crowd onto the same one
+#[derive(Default)]
+pub struct ShoreReservations(pub HashMap<usize, Entity>);
+
+#[derive(Component)]
+pub struct ShoreSpot(pub usize);
+
+impl DrinkingPlugin {
+    fn release_shore_spots(
+        mut query: Query<(Entity, &ShoreSpot, &Decision, &mut AnimalState)>,
+        mut reservations: ResMut<ShoreReservations>,
+        mut commands: Commands,
+    ) {
+        for (entity, _, decision, mut state) in query.iter_mut() {
+            if decision.action != Action::Drink {
+                if *state == AnimalState::Drinking {
+                    *state = AnimalState::Idle;
+                }
+                commands.entity(entity).remove::<ShoreSpot>();
+            }
+        }
+
+        // Also drops the spots of animals that died or gave up on them
+        reservations.0.retain(|tile, entity| {
+            query.get(*entity).is_ok_and(|(_, spot, decision, _)| {
+                spot.0 == *tile && decision.action == Action::Drink
+            })
+        });
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn seek_shore_spot(
+        query: Query<
+            (
+                Entity,
+                &Pos,
+                &Locomotion,
+                &Decision,
+                &Thirst,
+                &Perceived,
+                &Memory,
+            ),
+            (
+                Without<ShoreSpot>,
+                Without<Path>,
+                Without<PathRequest>,
+                Without<NearestPathRequest>,
+                Without<PathfindingTask>,
+            ),
+        >,
+        map: Res<Map>,
+        reservations: Res<ShoreReservations>,
+        mut commands: Commands,
+    ) {
+        for (entity, pos, locomotion, decision, thirst, perceived, memory) in query.iter() {
+            if decision.action != Action::Drink || thirst.0 < MIN_THIRST_TO_DRINK {
+                continue;
+            }
+
+            let mut shore_tiles = visible_shore_tiles(perceived, *locomotion, &map);
+            if map.in_bounds(pos.0) && map.is_next_to_water(vec2_to_idx(pos.0)) {
+                shore_tiles.insert(vec2_to_idx(pos.0));
+            }
+            shore_tiles.retain(|tile| !reservations.0.contains_key(tile));
+
+            // No water in sight, head back to where some was seen before
+            if shore_tiles.is_empty() && perceived.water.is_empty() {
+                if let Some(remembered) = memory.best(MemoryKind::Water, pos.0) {
+                    commands
+                        .entity(entity)
+                        .insert(PathRequest {
+                            goal: remembered.pos,
+                            options: PathOptions {
+                                max_nodes: Some(REMEMBERED_WATER_SEARCH_BUDGET),
+                                fallback_to_nearest: true,
+                                locomotion: *locomotion,
+                            },
+                        })
+                        .insert(RememberedGoal {
+                            kind: MemoryKind::Water,
+                            pos: remembered.pos,
+                        });
+                    continue;
+                }
+            }
+
+            // Every spot in sight is taken, wait for one to free up instead of searching for
+            // nothing and giving up on drinking
+            if shore_tiles.is_empty() {
+                continue;
+            }
+
+            // The spot gets claimed once the path to it is found, see claim_shore_spot
+            commands.entity(entity).insert(NearestPathRequest {
+                goals: shore_tiles
+                    .iter()
+                    .map(|tile| idx_to_vec2(*tile as i32))
+                    .collect(),
+                max_cost: SHORE_SEARCH_RADIUS,
+                options: PathOptions {
+                    locomotion: *locomotion,
+                    ..default()
+                },
+            });
+        }
+    }
+
+    // Takes the shore tile the new path ends on, unless another animal got to claim it while the
+    // path was being searched for. Then the animal looks for another spot.
+    #[allow(clippy::type_complexity)]
+    fn claim_shore_spot(
+        query: Query<(Entity, &PathOutcome, &Decision), (Changed<PathOutcome>, Without<ShoreSpot>)>,
+        map: Res<Map>,
+        mut reservations: ResMut<ShoreReservations>,
+        mut commands: Commands,
+    ) {
+        for (entity, outcome, decision) in query.iter() {
+            // Paths to remembered water can end anywhere
+            if decision.action != Action::Drink
+                || !map.in_bounds(outcome.end)
+                || !map.is_next_to_water(vec2_to_idx(outcome.end))
+            {
+                continue;
+            }
+
+            let spot = vec2_to_idx(outcome.end);
+            match reservations.0.get(&spot) {
+                Some(claimant) if *claimant != entity => {
+                    commands.entity(entity).remove::<Path>();
+                }
+                _ => {
+                    reservations.0.insert(spot, entity);
+                    commands.entity(entity).insert(ShoreSpot(spot));
+                }
+            }
+        }
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn start_drinking(
+        mut query: Query<
+            (
+                Entity,
+                &Pos,
+                &ShoreSpot,
+                &Decision,
+                &mut AnimalState,
+                &mut AnimalDirection,
+            ),
+            (Without<Path>, Without<PathfindingTask>, Without<Velocity>),
+        >,
+        map: Res<Map>,
+        animal_direction_map: Res<AnimalDirectionVectorMap>,
+        mut commands: Commands,
+    ) {
+        for (entity, pos, spot, decision, mut state, mut direction) in query.iter_mut() {
+            if decision.action != Action::Drink || *state == AnimalState::Drinking {
+                continue;
+            }
+
+            let spot_pos = idx_to_vec2(spot.0 as i32);
+            if (pos.0 - spot_pos).length() > SHORE_ARRIVAL_DISTANCE {
+                // Got pushed off the way somehow, look for a spot again
+                commands.entity(entity).remove::<ShoreSpot>();
+                continue;
+            }
+
+            if let Some(water_pos) = adjacent_water(spot.0, &map) {
+                *direction = animal_direction_map.closest_direction(water_pos - spot_pos);
+            }
+            *state = AnimalState::Drinking;
+        }
+    }
+
+    fn drink(
+        mut query: Query<(Entity, &mut Thirst, &mut AnimalState), With<ShoreSpot>>,
+        time: Res<Time>,
+        mut commands: Commands,
+    ) {
+        for (entity, mut thirst, mut state) in query.iter_mut() {
+            if *state != AnimalState::Drinking {
+                continue;
+            }
+
+            thirst.0 = (thirst.0 - DRINK_RATE * time.delta_seconds()).max(0.0);
+            if thirst.0 <= 0.0 {
+                *state = AnimalState::Idle;
+                commands.entity(entity).remove::<ShoreSpot>();
+            }
+        }
+    }
+}
+
+// Enterable land tiles right next to the water the animal can see
+pub fn visible_shore_tiles(
+    perceived: &Perceived,
+    locomotion: Locomotion,
+    map: &Map,
+) -> HashSet<usize> {
+    let mut shore_tiles = HashSet::new();
+    for water_pos in perceived.water.iter() {
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                let neighbour_pos = *water_pos + Vec2::new(dx as f32, dy as f32) * TILE_SIZE as f32;
+                if let Some(tile) = map.tile_at(neighbour_pos) {
+                    if tile.tile_type == TileType::Land && locomotion.can_enter(tile) {
+                        shore_tiles.insert(vec2_to_idx(neighbour_pos));
+                    }
+                }
+            }
+        }
+    }
+    shore_tiles
+}
+
+pub fn adjacent_water(idx: usize, map: &Map) -> Option<Vec2> {
+    let tile_pos = idx_to_vec2(idx as i32);
+    for (dx, dy) in [
+        (1, 0),
+        (-1, 0),
+        (0, 1),
+        (0, -1),
+        (1, 1),
+        (-1, 1),
+        (1, -1),
+        (-1, -1),
+    ] {
+        let neighbour_pos = tile_pos + Vec2::new(dx as f32, dy as f32) * TILE_SIZE as f32;
+        if map
+            .tile_at(neighbour_pos)
+            .is_some_and(|tile| tile.tile_type == TileType::Water)
+        {
+            return Some(neighbour_pos);
+        }
+    }
+    None
+}
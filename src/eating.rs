@@ -0,0 +1,257 @@
+use bevy::utils::HashMap;
+
+use crate::prelude::*;
+
+// Nutrition taken from a plant per second
+const BITE_RATE: f32 = 8.0;
+// In tiles of walking cost
+const FOOD_SEARCH_RADIUS: f32 = 60.0;
+const FOOD_REACH_DISTANCE: f32 = TILE_SIZE as f32 * 1.5;
+const REMEMBERED_FOOD_SEARCH_BUDGET: usize = 2000;
+
+pub struct EatingPlugin;
+
+impl Plugin for EatingPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(PlantClaims::default())
+            .add_system(Self::release_food_targets)
+            .add_system(Self::seek_food)
+            // Otherwise the release would drop the claim before the FoodTarget is in place
+            .add_system(Self::claim_food.after(Self::release_food_targets))
+            .add_system(Self::start_eating)
+            .add_system(Self::eat);
+    }
+}
+
+// Plant -> the animal that gets to eat it
+#[derive(Default)]
+pub struct PlantClaims(pub HashMap<Entity, Entity>);
+
+#[derive(Component)]
+pub struct FoodTarget(pub Entity);
+
+// A claimed plant can still be taken over by an animal that is closer to it
+fn can_claim(
+    entity: Entity,
+    pos: Vec2,
+    (plant, plant_pos): (Entity, Vec2),
+    claims: &PlantClaims,
+    claimant_query: &Query<&Pos, With<FoodTarget>>,
+) -> bool {
+    match claims.0.get(&plant) {
+        Some(claimant) if *claimant != entity => {
+            claimant_query.get(*claimant).map_or(true, |claimant_pos| {
+                (pos - plant_pos).length() < (claimant_pos.0 - plant_pos).length()
+            })
+        }
+        _ => true,
+    }
+}
+
+impl EatingPlugin {
+    fn release_food_targets(
+        mut query: Query<(Entity, &FoodTarget, &Decision, &mut AnimalState)>,
+        plant_query: Query<&Plant>,
+        mut claims: ResMut<PlantClaims>,
+        mut commands: Commands,
+    ) {
+        for (entity, food_target, decision, mut state) in query.iter_mut() {
+            let plant_gone = plant_query.get(food_target.0).is_err();
+            // Someone closer took the plant over
+            let lost_claim = claims.0.get(&food_target.0) != Some(&entity);
+            if decision.action != Action::Eat || plant_gone || lost_claim {
+                if *state == AnimalState::Eating {
+                    *state = AnimalState::Idle;
+                }
+                commands
+                    .entity(entity)
+                    .remove::<FoodTarget>()
+                    .remove::<Path>()
+                    .remove::<Velocity>();
+            }
+        }
+
+        claims.0.retain(|plant, animal| {
+            plant_query.get(*plant).is_ok()
+                && query
+                    .get(*animal)
+                    .is_ok_and(|(_, food_target, decision, _)| {
+                        food_target.0 == *plant && decision.action == Action::Eat
+                    })
+        });
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn seek_food(
+        query: Query<
+            (Entity, &Pos, &Locomotion, &Decision, &Perceived, &Memory),
+            (
+                Without<FoodTarget>,
+                Without<Path>,
+                Without<PathRequest>,
+                Without<NearestPathRequest>,
+                Without<PathfindingTask>,
+            ),
+        >,
+        claimant_query: Query<&Pos, With<FoodTarget>>,
+        claims: Res<PlantClaims>,
+        mut commands: Commands,
+    ) {
+        for (entity, pos, locomotion, decision, perceived, memory) in query.iter() {
+            if decision.action != Action::Eat {
+                continue;
+            }
+
+            // Nothing in sight, go and check on a plant seen before
+            if perceived.plants.is_empty() {
+                if let Some(remembered) = memory.best(MemoryKind::Food, pos.0) {
+                    commands
+                        .entity(entity)
+                        .insert(PathRequest {
+                            goal: remembered.pos,
+                            options: PathOptions {
+                                max_nodes: Some(REMEMBERED_FOOD_SEARCH_BUDGET),
+                                fallback_to_nearest: true,
+                                locomotion: *locomotion,
+                            },
+                        })
+                        .insert(RememberedGoal {
+                            kind: MemoryKind::Food,
+                            pos: remembered.pos,
+                        });
+                    continue;
+                }
+            }
+
+            let goals: Vec<Vec2> = perceived
+                .plants
+                .iter()
+                .filter(|plant| can_claim(entity, pos.0, **plant, &claims, &claimant_query))
+                .map(|(_, plant_pos)| *plant_pos)
+                .collect();
+            // Every plant in sight is taken, wait for one to free up instead of searching for
+            // nothing and giving up on eating
+            if goals.is_empty() {
+                continue;
+            }
+
+            // The plant gets claimed once the path to it is found, see claim_food
+            commands.entity(entity).insert(NearestPathRequest {
+                goals,
+                max_cost: FOOD_SEARCH_RADIUS,
+                options: PathOptions {
+                    locomotion: *locomotion,
+                    ..default()
+                },
+            });
+        }
+    }
+
+    // Claims the plant the new path leads to. When that is no longer possible, because a closer
+    // animal claimed it in the meantime, the animal looks for food again.
+    #[allow(clippy::type_complexity)]
+    fn claim_food(
+        query: Query<
+            (Entity, &Pos, &PathOutcome, &Decision, &Perceived),
+            (Changed<PathOutcome>, Without<FoodTarget>),
+        >,
+        claimant_query: Query<&Pos, With<FoodTarget>>,
+        mut claims: ResMut<PlantClaims>,
+        mut commands: Commands,
+    ) {
+        for (entity, pos, outcome, decision, perceived) in query.iter() {
+            if decision.action != Action::Eat {
+                continue;
+            }
+            let end_tile = vec2_to_idx(outcome.end);
+            let plant = perceived
+                .plants
+                .iter()
+                .filter(|(_, plant_pos)| vec2_to_idx(*plant_pos) == end_tile)
+                .find(|plant| can_claim(entity, pos.0, **plant, &claims, &claimant_query));
+            match plant {
+                Some((plant, _)) => {
+                    claims.0.insert(*plant, entity);
+                    commands.entity(entity).insert(FoodTarget(*plant));
+                }
+                // Paths to remembered food can end anywhere
+                None if perceived.plants.is_empty() => {}
+                None => {
+                    commands.entity(entity).remove::<Path>();
+                }
+            }
+        }
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn start_eating(
+        mut query: Query<
+            (
+                Entity,
+                &Pos,
+                &FoodTarget,
+                &mut AnimalState,
+                &mut AnimalDirection,
+            ),
+            (Without<Path>, Without<PathfindingTask>, Without<Velocity>),
+        >,
+        plant_query: Query<&Pos, With<Plant>>,
+        animal_direction_map: Res<AnimalDirectionVectorMap>,
+        mut commands: Commands,
+    ) {
+        for (entity, pos, food_target, mut state, mut direction) in query.iter_mut() {
+            if *state == AnimalState::Eating {
+                continue;
+            }
+            let plant_pos = match plant_query.get(food_target.0) {
+                Ok(plant_pos) => plant_pos.0,
+                Err(_) => continue,
+            };
+
+            if (plant_pos - pos.0).length() > FOOD_REACH_DISTANCE {
+                // Ran out of path without getting to the plant, look for food again
+                commands.entity(entity).remove::<FoodTarget>();
+                continue;
+            }
+
+            if plant_pos != pos.0 {
+                *direction = animal_direction_map.closest_direction(plant_pos - pos.0);
+            }
+            *state = AnimalState::Eating;
+        }
+    }
+
+    fn eat(
+        mut query: Query<(Entity, &FoodTarget, &Hunger, &mut AnimalState)>,
+        mut plant_query: Query<&mut Plant>,
+        mut ev_food_eaten: EventWriter<FoodEatenEvent>,
+        time: Res<Time>,
+        mut commands: Commands,
+    ) {
+        for (entity, food_target, hunger, mut state) in query.iter_mut() {
+            if *state != AnimalState::Eating {
+                continue;
+            }
+            let mut plant = match plant_query.get_mut(food_target.0) {
+                Ok(plant) => plant,
+                Err(_) => continue,
+            };
+
+            let bite = (BITE_RATE * time.delta_seconds()).min(plant.nutrition);
+            plant.nutrition -= bite;
+            ev_food_eaten.send(FoodEatenEvent {
+                entity,
+                nutrition: bite,
+            });
+
+            let plant_finished = plant.nutrition <= 0.0;
+            if plant_finished {
+                commands.entity(food_target.0).despawn();
+            }
+            if plant_finished || hunger.0 <= bite {
+                *state = AnimalState::Idle;
+                commands.entity(entity).remove::<FoodTarget>();
+            }
+        }
+    }
+}
@@ -0,0 +1,220 @@
+use std::f32::consts::TAU;
+
+use crate::prelude::*;
+
+const ESCAPE_DIRECTIONS: usize = 16;
+const ESCAPE_SEARCH_BUDGET: usize = 400;
+// Only the most promising directions get a path search, one after the other until a path is found
+const ESCAPE_ATTEMPTS: usize = 4;
+// In tiles
+const COVER_RADIUS: i32 = 2;
+const FLEE_REPLAN_INTERVAL: f32 = 1.0;
+
+pub struct FleePlugin;
+
+impl Plugin for FleePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(Self::detect_threats)
+            .add_system(Self::stop_fleeing)
+            .add_system(Self::flee);
+    }
+}
+
+// Predators the animal has seen recently, kept around for a while after they drop out of sight
+#[derive(Component)]
+pub struct Alarm {
+    pub threats: Vec<(Entity, Vec2)>,
+    last_seen_at: f64,
+}
+
+impl Alarm {
+    pub fn new(threats: Vec<(Entity, Vec2)>, now: f64) -> Self {
+        Alarm {
+            threats,
+            last_seen_at: now,
+        }
+    }
+
+    pub fn raise(&mut self, threats: Vec<(Entity, Vec2)>, now: f64) {
+        self.threats = threats;
+        self.last_seen_at = now;
+    }
+}
+
+#[derive(Component)]
+pub struct Fleeing {
+    replan_timer: Timer,
+}
+
+impl FleePlugin {
+    fn detect_threats(
+        mut query: Query<(Entity, &AnimalType, &Perceived, Option<&mut Alarm>)>,
+        species: Res<SpeciesRegistry>,
+        time: Res<Time>,
+        mut commands: Commands,
+    ) {
+        let now = time.seconds_since_startup();
+
+        for (entity, animal_type, perceived, alarm) in query.iter_mut() {
+            let threats: Vec<(Entity, Vec2)> = perceived
+                .animals
+                .iter()
+                .filter(|other| species.get(other.animal_type).hunts(*animal_type))
+                .map(|other| (other.entity, other.pos))
+                .collect();
+
+            match alarm {
+                Some(mut alarm) if !threats.is_empty() => alarm.raise(threats, now),
+                Some(alarm)
+                    if now - alarm.last_seen_at > species.get(*animal_type).flee.calm_down_time =>
+                {
+                    commands.entity(entity).remove::<Alarm>();
+                }
+                None if !threats.is_empty() => {
+                    commands.entity(entity).insert(Alarm::new(threats, now));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn stop_fleeing(query: Query<(Entity, &Decision), With<Fleeing>>, mut commands: Commands) {
+        for (entity, decision) in query.iter() {
+            if decision.action != Action::Flee {
+                commands
+                    .entity(entity)
+                    .remove::<Fleeing>()
+                    .insert(Gait::Walk);
+            }
+        }
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn flee(
+        mut query: Query<(
+            Entity,
+            &Pos,
+            &AnimalType,
+            &Locomotion,
+            &Decision,
+            &Alarm,
+            Option<&mut Fleeing>,
+            Option<&Path>,
+            Option<&PathRequest>,
+            Option<&PathfindingTask>,
+        )>,
+        map: Res<Map>,
+        species: Res<SpeciesRegistry>,
+        time: Res<Time>,
+        mut commands: Commands,
+    ) {
+        for (entity, pos, animal_type, locomotion, decision, alarm, fleeing, path, request, task) in
+            query.iter_mut()
+        {
+            // Keeps running along the current path while the new one is searched for
+            if decision.action != Action::Flee || request.is_some() || task.is_some() {
+                continue;
+            }
+            match fleeing {
+                Some(mut fleeing) => {
+                    fleeing.replan_timer.tick(time.delta());
+                    // Keep running the current way unless the threats had time to move
+                    if path.is_some() && !fleeing.replan_timer.just_finished() {
+                        continue;
+                    }
+                }
+                None => {
+                    commands
+                        .entity(entity)
+                        .insert(Fleeing {
+                            replan_timer: Timer::from_seconds(FLEE_REPLAN_INTERVAL, true),
+                        })
+                        .insert(Gait::Run);
+                }
+            }
+
+            let mut targets: VecDeque<Vec2> = Self::score_escape_targets(
+                pos.0,
+                *locomotion,
+                &species.get(*animal_type).flee,
+                &alarm.threats,
+                &map,
+            )
+            .into_iter()
+            .take(ESCAPE_ATTEMPTS)
+            .map(|(target, _)| target)
+            .collect();
+            if let Some(target) = targets.pop_front() {
+                commands
+                    .entity(entity)
+                    .insert(PathRequest {
+                        goal: target,
+                        options: PathOptions {
+                            max_nodes: Some(ESCAPE_SEARCH_BUDGET),
+                            fallback_to_nearest: true,
+                            locomotion: *locomotion,
+                        },
+                    })
+                    .insert(FallbackGoals(targets));
+            }
+        }
+    }
+
+    // Points in a ring around the animal, best first. Directions that lead away from the
+    // threats score higher, the closer a threat the more it counts, and so do spots next to trees
+    // where a predator would lose sight of the animal.
+    fn score_escape_targets(
+        pos: Vec2,
+        locomotion: Locomotion,
+        flee: &FleeDefinition,
+        threats: &[(Entity, Vec2)],
+        map: &Map,
+    ) -> Vec<(Vec2, f32)> {
+        let away = threats
+            .iter()
+            .fold(Vec2::ZERO, |away, (_, threat_pos)| {
+                let from_threat = pos - *threat_pos;
+                away + from_threat / from_threat.length_squared().max(1.0)
+            })
+            .normalize_or_zero();
+
+        let mut candidates: Vec<(Vec2, f32)> = (0..ESCAPE_DIRECTIONS)
+            .filter_map(|i| {
+                let angle = i as f32 / ESCAPE_DIRECTIONS as f32 * TAU;
+                let direction = Vec2::new(angle.cos(), angle.sin());
+                let target = pos + direction * flee.escape_distance();
+                let tile = map.tile_at(target)?;
+                if !locomotion.can_enter(tile) {
+                    return None;
+                }
+                let score = flee.away_weight * direction.dot(away)
+                    + flee.cover_weight * cover_around(target, map);
+                Some((target, score))
+            })
+            .collect();
+        candidates.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+        candidates
+    }
+}
+
+// Share of the surrounding tiles that have a tree on them
+pub fn cover_around(pos: Vec2, map: &Map) -> f32 {
+    let mut trees = 0;
+    let mut tiles = 0;
+    for dy in -COVER_RADIUS..=COVER_RADIUS {
+        for dx in -COVER_RADIUS..=COVER_RADIUS {
+            let offset = Vec2::new(dx as f32, dy as f32) * TILE_SIZE as f32;
+            if let Some(tile) = map.tile_at(pos + offset) {
+                tiles += 1;
+                if tile.has_tree() {
+                    trees += 1;
+                }
+            }
+        }
+    }
+    if tiles == 0 {
+        0.0
+    } else {
+        trees as f32 / tiles as f32
+    }
+}
@@ -0,0 +1,128 @@
+use rand_distr::{Distribution, Normal};
+use serde::Deserialize;
+
+use crate::prelude::*;
+
+// Relative spread of the genes in the founding population
+const INITIAL_VARIATION: f32 = 0.1;
+
+pub struct GeneticsPlugin;
+
+impl Plugin for GeneticsPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(MutationConfig::default());
+    }
+}
+
+pub struct MutationConfig {
+    // Chance of each gene to mutate when passed on
+    pub mutation_chance: f32,
+    // Standard deviation of a mutation, relative to the gene's value
+    pub mutation_strength: f32,
+    // Standard deviation of a mutation of a colour channel
+    pub tint_mutation_strength: f32,
+}
+
+impl Default for MutationConfig {
+    fn default() -> Self {
+        MutationConfig {
+            mutation_chance: 0.2,
+            mutation_strength: 0.1,
+            tint_mutation_strength: 0.05,
+        }
+    }
+}
+
+#[derive(Component, Clone, Debug, Deserialize)]
+pub struct Genome {
+    // World units per second
+    pub speed: f32,
+    // World units
+    pub sense_radius: f32,
+    // Multiplier on the species' sprite size
+    pub size: f32,
+    // Multiplier on how fast needs grow
+    pub metabolism: f32,
+    // Average amount of young per litter
+    pub litter_size: f32,
+    // How many places the animal can remember
+    pub memory_capacity: f32,
+    pub tint: [f32; 3],
+}
+
+impl Genome {
+    pub fn for_species(species: &SpeciesDefinition) -> Self {
+        let base = &species.base_genome;
+        let mut rng = thread_rng();
+        let variation = Normal::new(1.0, INITIAL_VARIATION).unwrap();
+        let mut vary = |gene: f32| gene * variation.sample(&mut rng);
+
+        Genome {
+            speed: vary(base.speed),
+            sense_radius: vary(base.sense_radius),
+            size: vary(base.size),
+            metabolism: vary(base.metabolism),
+            litter_size: vary(base.litter_size),
+            memory_capacity: vary(base.memory_capacity),
+            tint: base.tint,
+        }
+        .clamped()
+    }
+
+    // Every gene comes from one of the parents at random, then has a chance to mutate
+    pub fn crossover(mother: &Genome, father: &Genome, config: &MutationConfig) -> Self {
+        let mut rng = thread_rng();
+        let mutation = Normal::new(0.0, config.mutation_strength).unwrap();
+        let tint_mutation = Normal::new(0.0, config.tint_mutation_strength).unwrap();
+        let mut inherit = |mother_gene: f32, father_gene: f32| {
+            let gene = if rng.gen_bool(0.5) {
+                mother_gene
+            } else {
+                father_gene
+            };
+            if rng.gen::<f32>() < config.mutation_chance {
+                gene * (1.0 + mutation.sample(&mut rng))
+            } else {
+                gene
+            }
+        };
+
+        let mut genome = Genome {
+            speed: inherit(mother.speed, father.speed),
+            sense_radius: inherit(mother.sense_radius, father.sense_radius),
+            size: inherit(mother.size, father.size),
+            metabolism: inherit(mother.metabolism, father.metabolism),
+            litter_size: inherit(mother.litter_size, father.litter_size),
+            memory_capacity: inherit(mother.memory_capacity, father.memory_capacity),
+            tint: mother.tint,
+        };
+        for channel in 0..3 {
+            genome.tint[channel] = if rng.gen_bool(0.5) {
+                mother.tint[channel]
+            } else {
+                father.tint[channel]
+            };
+            if rng.gen::<f32>() < config.mutation_chance {
+                genome.tint[channel] += tint_mutation.sample(&mut rng);
+            }
+        }
+        genome.clamped()
+    }
+
+    fn clamped(mut self) -> Self {
+        self.speed = self.speed.max(1.0);
+        self.sense_radius = self.sense_radius.max(TILE_SIZE as f32);
+        self.size = self.size.clamp(0.5, 2.0);
+        self.metabolism = self.metabolism.max(0.25);
+        self.litter_size = self.litter_size.max(1.0);
+        self.memory_capacity = self.memory_capacity.max(1.0);
+        for channel in self.tint.iter_mut() {
+            *channel = channel.clamp(0.0, 1.0);
+        }
+        self
+    }
+
+    pub fn tint_color(&self) -> Color {
+        Color::rgb(self.tint[0], self.tint[1], self.tint[2])
+    }
+}
@@ -1,17 +1,50 @@
+use std::collections::HashMap as StdHashMap;
+
 use crate::prelude::*;
 use bevy::sprite::Anchor;
 use bevy::utils::HashMap;
+use serde::Deserialize;
 
 pub struct GraphicsPlugin;
 
-const BUNNY_SIZE: f32 = 10.0;
-const BUNNY_SIDE_HEIGHT_RATIO: f32 = 28.0 / 33.0;
-const BUNNY_FRONTBACK_HEIGHT_RATIO: f32 = 19.0 / 29.0;
+const PLANT_SIZE: f32 = 6.0;
+const PLANT_IMG_SIZE_RATIO: f32 = 90.0 / 100.0;
+
+// atlases[state][direction]
+pub type AnimalAtlases = HashMap<AnimalState, HashMap<AnimalDirection, Handle<TextureAtlas>>>;
 
 pub struct SpriteSheets {
     pub trees: Handle<TextureAtlas>,
     pub plants: Handle<TextureAtlas>,
-    pub bunny: HashMap<AnimalState, HashMap<AnimalDirection, Handle<TextureAtlas>>>, // bunny[state][direction]
+    pub animals: HashMap<AnimalType, AnimalAtlases>,
+}
+
+impl SpriteSheets {
+    pub fn animal_atlases(&self, animal_type: &AnimalType) -> &AnimalAtlases {
+        self.animals.get(animal_type).unwrap()
+    }
+}
+
+// How a species is drawn, part of its species file
+#[derive(Deserialize)]
+pub struct SpriteDefinition {
+    // Relative to the assets folder
+    pub sheet: String,
+    // Height of the sprite, in world units
+    pub size: f32,
+    pub side_height_ratio: f32,
+    pub front_back_height_ratio: f32,
+    // Seconds per frame
+    pub frame_time: f32,
+    pub animations: StdHashMap<AnimalState, StdHashMap<AnimalDirection, AnimationFrames>>,
+}
+
+// A row of frames in the sheet, in pixels
+#[derive(Deserialize)]
+pub struct AnimationFrames {
+    pub frame_size: (f32, f32),
+    pub frames: usize,
+    pub offset: (f32, f32),
 }
 
 #[derive(Component)]
@@ -20,18 +53,17 @@ pub struct FrameAnimation {
     current_frame: usize,
 }
 
+// Only read by _draw_paths, which is switched off
+#[allow(dead_code)]
 pub struct DrawPathEvent(pub Path);
 
-pub struct AdjustSpriteSizeEvent(Entity, AnimalDirection);
-
 impl Plugin for GraphicsPlugin {
     fn build(&self, app: &mut App) {
         app.add_event::<DrawPathEvent>()
-            .add_event::<AdjustSpriteSizeEvent>()
             .add_startup_system_to_stage(StartupStage::PreStartup, Self::load_spritesheets)
             .add_startup_system_to_stage(StartupStage::Startup, Self::render_map)
             .add_startup_system_to_stage(StartupStage::Startup, Self::render_trees)
-            .add_startup_system_to_stage(StartupStage::Startup, spawn_animal_sprites)
+            .add_system(spawn_animal_sprites)
             .add_system(Self::frame_animation)
             .add_system(Self::adjust_sprite_sizes)
             // .add_system(Self::draw_paths)
@@ -39,28 +71,34 @@ impl Plugin for GraphicsPlugin {
     }
 }
 
+#[allow(clippy::type_complexity)]
 fn spawn_animal_sprites(
     mut commands: Commands,
     atlases: Res<SpriteSheets>,
-    query: Query<(
-        Entity,
-        &Animal,
-        &Pos,
-        &AnimalState,
-        &AnimalDirection,
-        &AnimalType,
-    )>,
+    species: Res<SpeciesRegistry>,
+    query: Query<
+        (
+            Entity,
+            &Animal,
+            &Pos,
+            &AnimalState,
+            &AnimalDirection,
+            &AnimalType,
+            &LifeStage,
+            &Genome,
+        ),
+        Added<Animal>,
+    >,
 ) {
     // later, instead of reading from query we should be reading from some config file
     // query is just a dirty workaround here, cause I don't want to figure out how to create such
     // config file yet
-    query
-        .iter()
-        .for_each(|(entity, _, pos, state, direction, animal_type)| {
-            let animal_atlases = match animal_type {
-                AnimalType::Bunny => &atlases.bunny,
-            };
-            let sprite_size = GraphicsPlugin::get_sprite_size(direction, animal_type);
+    query.iter().for_each(
+        |(entity, _, pos, state, direction, animal_type, life_stage, genome)| {
+            let animal_atlases = atlases.animal_atlases(animal_type);
+            let sprite = &species.get(*animal_type).sprite;
+            let sprite_size =
+                GraphicsPlugin::get_sprite_size(direction, sprite, life_stage) * genome.size;
 
             let direction_map = animal_atlases.get(state).unwrap();
             let target_atlas = direction_map.get(direction).unwrap();
@@ -69,6 +107,7 @@ fn spawn_animal_sprites(
                 .insert_bundle(SpriteSheetBundle {
                     sprite: TextureAtlasSprite {
                         custom_size: Some(sprite_size),
+                        color: genome.tint_color(),
                         index: 0,
                         ..default()
                     },
@@ -81,10 +120,11 @@ fn spawn_animal_sprites(
                     ..default()
                 })
                 .insert(FrameAnimation {
-                    timer: Timer::from_seconds(0.1, true),
+                    timer: Timer::from_seconds(sprite.frame_time, true),
                     current_frame: 0,
                 });
-        })
+        },
+    )
 }
 impl GraphicsPlugin {
     fn adjust_sprite_sizes(
@@ -92,34 +132,46 @@ impl GraphicsPlugin {
             &Animal,
             &AnimalType,
             &AnimalDirection,
+            &LifeStage,
+            &Genome,
             &mut TextureAtlasSprite,
         )>,
+        species: Res<SpeciesRegistry>,
     ) {
-        for (_, animal_type, direction, mut sprite) in query.iter_mut() {
-            sprite.custom_size = Some(Self::get_sprite_size(direction, animal_type));
+        for (_, animal_type, direction, life_stage, genome, mut sprite) in query.iter_mut() {
+            let definition = &species.get(*animal_type).sprite;
+            sprite.custom_size =
+                Some(Self::get_sprite_size(direction, definition, life_stage) * genome.size);
         }
     }
 
-    pub fn get_sprite_size(direction: &AnimalDirection, animal_type: &AnimalType) -> Vec2 {
-        match animal_type {
-            AnimalType::Bunny => match direction {
-                AnimalDirection::Down | AnimalDirection::Up => {
-                    Vec2::new(BUNNY_SIZE * BUNNY_FRONTBACK_HEIGHT_RATIO, BUNNY_SIZE)
-                }
-                AnimalDirection::Left | AnimalDirection::Right => {
-                    Vec2::new(BUNNY_SIZE, BUNNY_SIZE * BUNNY_SIDE_HEIGHT_RATIO)
-                }
-            },
+    pub fn get_sprite_size(
+        direction: &AnimalDirection,
+        sprite: &SpriteDefinition,
+        life_stage: &LifeStage,
+    ) -> Vec2 {
+        let size = sprite.size * life_stage.size_factor();
+        match direction {
+            AnimalDirection::Down | AnimalDirection::Up => {
+                Vec2::new(size * sprite.front_back_height_ratio, size)
+            }
+            AnimalDirection::Left | AnimalDirection::Right => {
+                Vec2::new(size, size * sprite.side_height_ratio)
+            }
         }
     }
 
+    pub fn get_plant_size(scale: f32) -> Vec2 {
+        Vec2::new(PLANT_SIZE * PLANT_IMG_SIZE_RATIO, PLANT_SIZE) * scale
+    }
+
     fn load_spritesheets(
         mut commands: Commands,
         asset_server: Res<AssetServer>,
         mut texture_atlases: ResMut<Assets<TextureAtlas>>,
+        species: Res<SpeciesRegistry>,
     ) {
         let tree_sprite_sheet_handle: Handle<Image> = asset_server.load("tree_sprites.png");
-        let bunny_sprite_sheet_handle: Handle<Image> = asset_server.load("bunnysheet.png");
         let plants_sprite_sheet_handle: Handle<Image> = asset_server.load("plants.png");
 
         let tree_texture_atlas =
@@ -130,157 +182,34 @@ impl GraphicsPlugin {
             TextureAtlas::from_grid(plants_sprite_sheet_handle, Vec2::new(90.0, 100.0), 8, 1);
         let plants_texture_atlas_handle = texture_atlases.add(plants_texture_atlas);
 
-        let bunny_down_texture_atlas = TextureAtlas::from_grid_with_padding(
-            bunny_sprite_sheet_handle.clone(),
-            Vec2::new(19.0, 29.0),
-            5,
-            1,
-            Vec2::ZERO,
-            Vec2::ZERO,
-        );
-        let bunny_up_texture_atlas = TextureAtlas::from_grid_with_padding(
-            bunny_sprite_sheet_handle.clone(),
-            Vec2::new(19.0, 35.0),
-            5,
-            1,
-            Vec2::ZERO,
-            Vec2::new(0.0, 29.0),
-        );
-        let bunny_right_texture_atlas = TextureAtlas::from_grid_with_padding(
-            bunny_sprite_sheet_handle.clone(),
-            Vec2::new(33.0, 28.0),
-            5,
-            1,
-            Vec2::ZERO,
-            Vec2::new(0.0, 64.0),
-        );
-        let bunny_left_texture_atlas = TextureAtlas::from_grid_with_padding(
-            bunny_sprite_sheet_handle.clone(),
-            Vec2::new(33.0, 28.0),
-            5,
-            1,
-            Vec2::ZERO,
-            Vec2::new(0.0, 92.0),
-        );
-
-        let bunny_down_eat_texture_atlas = TextureAtlas::from_grid_with_padding(
-            bunny_sprite_sheet_handle.clone(),
-            Vec2::new(19.0, 29.0),
-            3,
-            1,
-            Vec2::ZERO,
-            Vec2::new(95.0, 0.0),
-        );
-        let bunny_up_eat_texture_atlas = TextureAtlas::from_grid_with_padding(
-            bunny_sprite_sheet_handle.clone(),
-            Vec2::new(19.0, 35.0),
-            3,
-            1,
-            Vec2::ZERO,
-            Vec2::new(95.0, 29.0),
-        );
-        let bunny_right_eat_texture_atlas = TextureAtlas::from_grid_with_padding(
-            bunny_sprite_sheet_handle.clone(),
-            Vec2::new(28.0, 22.0),
-            3,
-            1,
-            Vec2::ZERO,
-            Vec2::new(165.0, 64.0),
-        );
-        let bunny_left_eat_texture_atals = TextureAtlas::from_grid_with_padding(
-            bunny_sprite_sheet_handle.clone(),
-            Vec2::new(28.0, 22.0),
-            3,
-            1,
-            Vec2::ZERO,
-            Vec2::new(165.0, 86.0),
-        );
-
-        let bunny_down_idle_texture_atlas = TextureAtlas::from_grid_with_padding(
-            bunny_sprite_sheet_handle.clone(),
-            Vec2::new(19.0, 26.0),
-            1,
-            1,
-            Vec2::ZERO,
-            Vec2::new(0.0, 128.0),
-        );
-        let bunny_up_idle_texture_atlas = TextureAtlas::from_grid_with_padding(
-            bunny_sprite_sheet_handle.clone(),
-            Vec2::new(20.0, 28.0),
-            1,
-            1,
-            Vec2::ZERO,
-            Vec2::new(19.0, 128.0),
-        );
-        let bunny_left_idle_texture_atlas = TextureAtlas::from_grid_with_padding(
-            bunny_sprite_sheet_handle.clone(),
-            Vec2::new(25.0, 27.0),
-            1,
-            1,
-            Vec2::ZERO,
-            Vec2::new(38.0, 128.0),
-        );
-        let bunny_right_idle_texture_atlas = TextureAtlas::from_grid_with_padding(
-            bunny_sprite_sheet_handle.clone(),
-            Vec2::new(25.0, 27.0),
-            1,
-            1,
-            Vec2::ZERO,
-            Vec2::new(63.0, 128.0),
-        );
-
-        let bunny_down_textrue_atlas_handle = texture_atlases.add(bunny_down_texture_atlas);
-        let bunny_up_texture_atlas_handle = texture_atlases.add(bunny_up_texture_atlas);
-        let bunny_right_texture_atlas_handle = texture_atlases.add(bunny_right_texture_atlas);
-        let bunny_left_texture_atlas_hanlde = texture_atlases.add(bunny_left_texture_atlas);
-
-        let bunny_down_eat_texture_atlas_handle = texture_atlases.add(bunny_down_eat_texture_atlas);
-        let bunny_up_eat_texture_atlas_handle = texture_atlases.add(bunny_up_eat_texture_atlas);
-        let bunny_right_eat_texture_atlas_handle =
-            texture_atlases.add(bunny_right_eat_texture_atlas);
-        let bunny_left_eat_texture_atals_handle = texture_atlases.add(bunny_left_eat_texture_atals);
-
-        let bunny_down_idle_texture_atlas_handle =
-            texture_atlases.add(bunny_down_idle_texture_atlas);
-        let bunny_up_idle_texture_atlas_handle = texture_atlases.add(bunny_up_idle_texture_atlas);
-        let bunny_left_idle_texture_atlas_handle =
-            texture_atlases.add(bunny_left_idle_texture_atlas);
-        let bunny_right_idle_texture_atlas_handle =
-            texture_atlases.add(bunny_right_idle_texture_atlas);
-
-        let mut bunny_atlas_map = HashMap::new();
-
-        let mut hop_atlases = HashMap::new();
-        hop_atlases.insert(AnimalDirection::Down, bunny_down_textrue_atlas_handle);
-        hop_atlases.insert(AnimalDirection::Up, bunny_up_texture_atlas_handle);
-        hop_atlases.insert(AnimalDirection::Left, bunny_left_texture_atlas_hanlde);
-        hop_atlases.insert(AnimalDirection::Right, bunny_right_texture_atlas_handle);
-
-        bunny_atlas_map.insert(AnimalState::Moving, hop_atlases);
-
-        let mut eat_atlases = HashMap::new();
-        eat_atlases.insert(AnimalDirection::Down, bunny_down_eat_texture_atlas_handle);
-        eat_atlases.insert(AnimalDirection::Up, bunny_up_eat_texture_atlas_handle);
-        eat_atlases.insert(AnimalDirection::Left, bunny_left_eat_texture_atals_handle);
-        eat_atlases.insert(AnimalDirection::Right, bunny_right_eat_texture_atlas_handle);
-
-        bunny_atlas_map.insert(AnimalState::Eating, eat_atlases);
-
-        let mut idle_atlases = HashMap::new();
-        idle_atlases.insert(AnimalDirection::Down, bunny_down_idle_texture_atlas_handle);
-        idle_atlases.insert(AnimalDirection::Up, bunny_up_idle_texture_atlas_handle);
-        idle_atlases.insert(AnimalDirection::Left, bunny_left_idle_texture_atlas_handle);
-        idle_atlases.insert(
-            AnimalDirection::Right,
-            bunny_right_idle_texture_atlas_handle,
-        );
-
-        bunny_atlas_map.insert(AnimalState::Idle, idle_atlases);
+        // Species that share a sheet share the image, the asset server only loads it once
+        let mut animals = HashMap::new();
+        for animal_type in species.all() {
+            let sprite = &species.get(animal_type).sprite;
+            let sheet_handle: Handle<Image> = asset_server.load(sprite.sheet.as_str());
+            let mut atlas_map = HashMap::new();
+            for (state, directions) in sprite.animations.iter() {
+                let mut direction_atlases = HashMap::new();
+                for (direction, frames) in directions.iter() {
+                    let atlas = TextureAtlas::from_grid_with_padding(
+                        sheet_handle.clone(),
+                        Vec2::from(frames.frame_size),
+                        frames.frames,
+                        1,
+                        Vec2::ZERO,
+                        Vec2::from(frames.offset),
+                    );
+                    direction_atlases.insert(direction.clone(), texture_atlases.add(atlas));
+                }
+                atlas_map.insert(*state, direction_atlases);
+            }
+            animals.insert(animal_type, atlas_map);
+        }
 
         commands.insert_resource(SpriteSheets {
             trees: tree_texture_atlas_handle,
             plants: plants_texture_atlas_handle,
-            bunny: bunny_atlas_map,
+            animals,
         });
         println!("Spritesheets are loaded!");
     }
@@ -334,6 +263,7 @@ impl GraphicsPlugin {
         });
     }
 
+    #[allow(clippy::type_complexity)]
     fn frame_animation(
         mut sprites_query: Query<(
             &mut TextureAtlasSprite,
@@ -356,9 +286,7 @@ impl GraphicsPlugin {
             animal_direction,
         ) in sprites_query.iter_mut()
         {
-            let animal_atlases = match animal_type {
-                AnimalType::Bunny => &atlases.bunny,
-            };
+            let animal_atlases = atlases.animal_atlases(animal_type);
 
             let direction_map = animal_atlases.get(animal_state).unwrap();
             let target_atlas = direction_map.get(animal_direction).unwrap();
@@ -0,0 +1,277 @@
+use bevy::utils::{HashMap, HashSet};
+
+use crate::prelude::*;
+
+const HERD_NEIGHBOUR_RADIUS: f32 = (TILE_SIZE * 6) as f32;
+const HERD_SEPARATION_RADIUS: f32 = (TILE_SIZE * 2) as f32;
+// Followers this close to a leader that isn't going anywhere stop and stay put
+const FOLLOW_DISTANCE: f32 = (TILE_SIZE * 3) as f32;
+// A follower that ends up further than this from its leader leaves the herd
+const HERD_BREAK_DISTANCE: f32 = (TILE_SIZE * 30) as f32;
+const FOLLOW_SEARCH_BUDGET: usize = 600;
+const LEADER_STOPPED_SPEED: f32 = 1.0;
+
+const LEADER_WEIGHT: f32 = 1.2;
+const COHESION_WEIGHT: f32 = 1.0;
+const ALIGNMENT_WEIGHT: f32 = 0.8;
+const SEPARATION_WEIGHT: f32 = 1.5;
+
+pub struct HerdPlugin;
+
+impl Plugin for HerdPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(Self::form_herds)
+            .add_system(Self::update_herds)
+            .add_system(Self::follow_leader)
+            .add_system(Self::share_alarms);
+    }
+}
+
+#[derive(Component, Clone, Copy)]
+pub struct Herd {
+    // The leader is a member of its own herd
+    pub leader: Entity,
+}
+
+impl Herd {
+    pub fn is_leader(&self, entity: Entity) -> bool {
+        self.leader == entity
+    }
+}
+
+impl HerdPlugin {
+    // Loners join a herd they can see, or start a new one with another loner
+    fn form_herds(
+        query: Query<(Entity, &AnimalType, &Perceived, Option<&Herd>)>,
+        species: Res<SpeciesRegistry>,
+        mut commands: Commands,
+    ) {
+        let mut sizes: HashMap<Entity, usize> = HashMap::new();
+        for (_, _, _, herd) in query.iter() {
+            if let Some(herd) = herd {
+                *sizes.entry(herd.leader).or_default() += 1;
+            }
+        }
+
+        let mut joined: HashSet<Entity> = HashSet::new();
+        for (entity, animal_type, perceived, herd) in query.iter() {
+            if herd.is_some() || joined.contains(&entity) {
+                continue;
+            }
+            let max_herd_size = match species.get(*animal_type).max_herd_size {
+                Some(max_herd_size) => max_herd_size,
+                None => continue,
+            };
+            let same_species = || {
+                perceived
+                    .animals
+                    .iter()
+                    .filter(|other| other.animal_type == *animal_type)
+            };
+
+            let herd_in_sight = same_species()
+                .filter_map(|other| query.get(other.entity).ok())
+                .filter_map(|(_, _, _, other_herd)| other_herd.copied())
+                .find(|other_herd| {
+                    sizes.get(&other_herd.leader).copied().unwrap_or(0) < max_herd_size
+                });
+            if let Some(herd) = herd_in_sight {
+                *sizes.entry(herd.leader).or_default() += 1;
+                joined.insert(entity);
+                commands.entity(entity).insert(herd);
+                continue;
+            }
+
+            let loner = same_species().find(|other| {
+                !joined.contains(&other.entity)
+                    && query
+                        .get(other.entity)
+                        .is_ok_and(|(_, _, _, other_herd)| other_herd.is_none())
+            });
+            if let Some(loner) = loner {
+                let herd = Herd { leader: entity };
+                sizes.insert(entity, 2);
+                joined.insert(entity);
+                joined.insert(loner.entity);
+                commands.entity(entity).insert(herd);
+                commands.entity(loner.entity).insert(herd);
+            }
+        }
+    }
+
+    // Picks a new leader when the old one is gone and lets stragglers and lone leaders go
+    fn update_herds(
+        query: Query<(Entity, &Herd, &Pos)>,
+        selected_query: Query<(), With<SelectedAnimal>>,
+        mut commands: Commands,
+    ) {
+        let mut new_leaders: HashMap<Entity, Entity> = HashMap::new();
+        let mut led: HashSet<Entity> = HashSet::new();
+
+        for (entity, herd, pos) in query.iter() {
+            if herd.is_leader(entity) {
+                continue;
+            }
+            match query.get(herd.leader) {
+                Ok((_, leader_herd, leader_pos)) if leader_herd.is_leader(herd.leader) => {
+                    if (leader_pos.0 - pos.0).length() > HERD_BREAK_DISTANCE {
+                        commands.entity(entity).remove::<Herd>();
+                    } else {
+                        led.insert(herd.leader);
+                    }
+                }
+                _ => {
+                    let new_leader = *new_leaders.entry(herd.leader).or_insert(entity);
+                    if new_leader == entity {
+                        if selected_query.contains(entity) {
+                            println!("{:?} took over its herd", entity);
+                        }
+                    } else {
+                        led.insert(new_leader);
+                    }
+                    commands.entity(entity).insert(Herd { leader: new_leader });
+                }
+            }
+        }
+
+        for (entity, herd, _) in query.iter() {
+            let new_leader_without_herd =
+                new_leaders.values().any(|leader| *leader == entity) && !led.contains(&entity);
+            if (herd.is_leader(entity) && !led.contains(&entity)) || new_leader_without_herd {
+                commands.entity(entity).remove::<Herd>();
+            }
+        }
+    }
+
+    // Boids: followers are pulled towards their leader and the middle of the herd, match the
+    // heading of the others and keep some distance from them
+    #[allow(clippy::type_complexity)]
+    fn follow_leader(
+        query: Query<(
+            Entity,
+            &Herd,
+            &Pos,
+            &Locomotion,
+            &Decision,
+            Option<&Path>,
+            Option<&PathfindingTask>,
+        )>,
+        member_query: Query<(&Herd, &Pos, &Momentum)>,
+        grid: Res<SpatialGrid>,
+        pathfinder: Res<Pathfinder>,
+        mut commands: Commands,
+    ) {
+        for (entity, herd, pos, locomotion, decision, path, task) in query.iter() {
+            if herd.is_leader(entity) || decision.action != Action::Wander || task.is_some() {
+                continue;
+            }
+            let (_, leader_pos, leader_momentum) = match member_query.get(herd.leader) {
+                Ok(leader) => leader,
+                Err(_) => continue,
+            };
+
+            // Something is in the way, go around it on a path and flock again once past it
+            if !pathfinder.is_segment_clear(pos.0, leader_pos.0, *locomotion) {
+                if path.is_none() {
+                    commands.entity(entity).insert(PathRequest {
+                        goal: leader_pos.0,
+                        options: PathOptions {
+                            max_nodes: Some(FOLLOW_SEARCH_BUDGET),
+                            fallback_to_nearest: true,
+                            locomotion: *locomotion,
+                        },
+                    });
+                }
+                continue;
+            }
+            if path.is_some() {
+                commands.entity(entity).remove::<Path>();
+            }
+
+            let to_leader = leader_pos.0 - pos.0;
+            if to_leader.length() <= FOLLOW_DISTANCE && leader_momentum.speed < LEADER_STOPPED_SPEED
+            {
+                commands.entity(entity).remove::<Velocity>();
+                continue;
+            }
+
+            let mut center = Vec2::ZERO;
+            let mut heading = Vec2::ZERO;
+            let mut separation = Vec2::ZERO;
+            let mut neighbours = 0;
+            for (other, other_pos) in grid.nearby(pos.0, HERD_NEIGHBOUR_RADIUS) {
+                if other == entity {
+                    continue;
+                }
+                let (other_herd, _, other_momentum) = match member_query.get(other) {
+                    Ok(member) => member,
+                    Err(_) => continue,
+                };
+                if other_herd.leader != herd.leader {
+                    continue;
+                }
+                neighbours += 1;
+                center += other_pos;
+                heading += other_momentum.velocity();
+                let offset = pos.0 - other_pos;
+                let distance = offset.length();
+                if distance < HERD_SEPARATION_RADIUS && distance > f32::EPSILON {
+                    separation += offset / (distance * distance);
+                }
+            }
+
+            let mut direction = to_leader.normalize_or_zero() * LEADER_WEIGHT
+                + separation * HERD_SEPARATION_RADIUS * SEPARATION_WEIGHT;
+            if neighbours > 0 {
+                let cohesion = center / neighbours as f32 - pos.0;
+                direction += cohesion.normalize_or_zero() * COHESION_WEIGHT
+                    + heading.normalize_or_zero() * ALIGNMENT_WEIGHT;
+            }
+            let direction = direction.normalize_or_zero();
+            if direction != Vec2::ZERO {
+                commands.entity(entity).insert(Velocity(direction));
+            }
+        }
+    }
+
+    // Every member knows about every predator any member of the herd can see
+    fn share_alarms(
+        query: Query<(Entity, &Herd, &AnimalType, &Perceived)>,
+        mut alarm_query: Query<&mut Alarm>,
+        species: Res<SpeciesRegistry>,
+        time: Res<Time>,
+        mut commands: Commands,
+    ) {
+        let now = time.seconds_since_startup();
+        let mut threats: HashMap<Entity, Vec<(Entity, Vec2)>> = HashMap::new();
+        for (_, herd, animal_type, perceived) in query.iter() {
+            for other in perceived.animals.iter() {
+                if !species.get(other.animal_type).hunts(*animal_type) {
+                    continue;
+                }
+                let herd_threats = threats.entry(herd.leader).or_default();
+                if !herd_threats
+                    .iter()
+                    .any(|(threat, _)| *threat == other.entity)
+                {
+                    herd_threats.push((other.entity, other.pos));
+                }
+            }
+        }
+
+        for (entity, herd, _, _) in query.iter() {
+            let herd_threats = match threats.get(&herd.leader) {
+                Some(herd_threats) => herd_threats.clone(),
+                None => continue,
+            };
+            match alarm_query.get_mut(entity) {
+                Ok(mut alarm) => alarm.raise(herd_threats, now),
+                Err(_) => {
+                    commands
+                        .entity(entity)
+                        .insert(Alarm::new(herd_threats, now));
+                }
+            }
+        }
+    }
+}
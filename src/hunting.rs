@@ -0,0 +1,459 @@
+use std::f32::consts::PI;
+
+use bevy::utils::HashSet;
+
+use crate::prelude::*;
+
+// A sleeping animal doesn't get away in time
+const SLEEPING_VULNERABILITY: f32 = 2.0;
+// How often the pursuit is re-planned while the prey keeps moving
+const PURSUIT_REPLAN_INTERVAL: f32 = 0.5;
+// Caps how far ahead of a running prey the hunter aims, in seconds
+const MAX_PURSUIT_LEAD_TIME: f32 = 1.5;
+// Nodes the pursuit search may expand
+const PURSUIT_SEARCH_BUDGET: usize = 1500;
+// Seconds the prey can stay out of sight before the hunter gives up on it
+const LOST_PREY_TIMEOUT: f64 = 3.0;
+const CARCASS_NUTRITION: f32 = 80.0;
+const CARCASS_ROT_TIME: f32 = 60.0;
+const CARCASS_REACH_DISTANCE: f32 = TILE_SIZE as f32 * 1.5;
+// Nutrition taken from a carcass per second
+const FEED_RATE: f32 = 12.0;
+const REMEMBERED_CARCASS_SEARCH_BUDGET: usize = 2000;
+
+pub struct HuntingPlugin;
+
+impl Plugin for HuntingPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(Self::release_hunts)
+            .add_system(Self::choose_prey)
+            .add_system(Self::chase_prey)
+            .add_system(Self::feed)
+            .add_system(Self::rot_carcasses);
+    }
+}
+
+#[derive(Component)]
+pub struct Hunt {
+    pub prey: Entity,
+    pub last_seen_pos: Vec2,
+    last_seen_at: f64,
+    replan_timer: Timer,
+}
+
+#[derive(Component)]
+pub struct Carcass {
+    pub nutrition: f32,
+    rot_timer: Timer,
+}
+
+// The carcass the animal is eating or on its way to
+#[derive(Component)]
+pub struct Feeding(pub Entity);
+
+impl HuntingPlugin {
+    #[allow(clippy::type_complexity)]
+    fn release_hunts(
+        mut query: Query<(
+            Entity,
+            &Decision,
+            &mut AnimalState,
+            Option<&Hunt>,
+            Option<&Feeding>,
+        )>,
+        prey_query: Query<&Animal>,
+        carcass_query: Query<&Carcass>,
+        mut commands: Commands,
+    ) {
+        for (entity, decision, mut state, hunt, feeding) in query.iter_mut() {
+            let hunting = decision.action == Action::Hunt;
+            // Someone else might have caught the prey or finished the carcass first
+            if hunt.is_some_and(|hunt| !hunting || prey_query.get(hunt.prey).is_err()) {
+                commands.entity(entity).remove::<Hunt>().insert(Gait::Walk);
+            }
+            if feeding.is_some_and(|feeding| !hunting || carcass_query.get(feeding.0).is_err()) {
+                if *state == AnimalState::Eating {
+                    *state = AnimalState::Idle;
+                }
+                commands.entity(entity).remove::<Feeding>();
+            }
+        }
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn choose_prey(
+        query: Query<
+            (
+                Entity,
+                &Pos,
+                &AnimalType,
+                &Locomotion,
+                &Decision,
+                &Perceived,
+                &Memory,
+                (
+                    Option<&Path>,
+                    Option<&PathRequest>,
+                    Option<&PathfindingTask>,
+                ),
+            ),
+            (Without<Hunt>, Without<Feeding>),
+        >,
+        prey_query: Query<(&LifeStage, &AnimalState)>,
+        species: Res<SpeciesRegistry>,
+        time: Res<Time>,
+        mut commands: Commands,
+    ) {
+        // Distances are divided by the weight, so a weak animal further away can still be the
+        // better pick
+        let closest = |pos: Vec2, candidates: &mut dyn Iterator<Item = (Entity, Vec2, f32)>| {
+            candidates
+                .min_by(|(_, a, a_weight), (_, b, b_weight)| {
+                    ((*a - pos).length() / a_weight)
+                        .partial_cmp(&((*b - pos).length() / b_weight))
+                        .unwrap_or(std::cmp::Ordering::Equal)
+                })
+                .map(|(entity, pos, _)| (entity, pos))
+        };
+
+        for (entity, pos, animal_type, locomotion, decision, perceived, memory, travelling) in
+            query.iter()
+        {
+            if decision.action != Action::Hunt {
+                continue;
+            }
+
+            // Leftovers are a free meal, no need to chase anything
+            let mut carcasses = perceived
+                .carcasses
+                .iter()
+                .map(|(carcass, carcass_pos)| (*carcass, *carcass_pos, 1.0));
+            if let Some((carcass, _)) = closest(pos.0, &mut carcasses) {
+                commands
+                    .entity(entity)
+                    .remove::<Path>()
+                    .remove::<Velocity>()
+                    .insert(Feeding(carcass));
+                continue;
+            }
+
+            let mut prey = perceived
+                .animals
+                .iter()
+                .filter(|other| species.get(*animal_type).hunts(other.animal_type))
+                .map(|other| {
+                    let vulnerability = prey_query
+                        .get(other.entity)
+                        .map_or(1.0, |(life_stage, state)| vulnerability(life_stage, state));
+                    (other.entity, other.pos, vulnerability)
+                });
+            if let Some((prey, prey_pos)) = closest(pos.0, &mut prey) {
+                commands.entity(entity).insert(Hunt {
+                    prey,
+                    last_seen_pos: prey_pos,
+                    last_seen_at: time.seconds_since_startup(),
+                    replan_timer: Timer::from_seconds(PURSUIT_REPLAN_INTERVAL, true),
+                });
+                continue;
+            }
+
+            // Nothing to eat in sight, go back to a carcass seen earlier
+            let (path, request, task) = travelling;
+            if path.is_some() || request.is_some() || task.is_some() {
+                continue;
+            }
+            if let Some(remembered) = memory.best(MemoryKind::Food, pos.0) {
+                commands
+                    .entity(entity)
+                    .insert(PathRequest {
+                        goal: remembered.pos,
+                        options: PathOptions {
+                            max_nodes: Some(REMEMBERED_CARCASS_SEARCH_BUDGET),
+                            fallback_to_nearest: true,
+                            locomotion: *locomotion,
+                        },
+                    })
+                    .insert(RememberedGoal {
+                        kind: MemoryKind::Food,
+                        pos: remembered.pos,
+                    });
+            }
+        }
+    }
+
+    #[allow(clippy::type_complexity, clippy::too_many_arguments)]
+    fn chase_prey(
+        mut query: Query<(
+            Entity,
+            &Pos,
+            &AnimalType,
+            &Locomotion,
+            &MovementStats,
+            &Perceived,
+            &mut Hunt,
+            Option<&Path>,
+            Option<&PathfindingTask>,
+        )>,
+        prey_query: Query<(
+            &Pos,
+            &AnimalType,
+            &LifeStage,
+            &AnimalState,
+            &Genome,
+            &Momentum,
+        )>,
+        pathfinder: Res<Pathfinder>,
+        sprite_sheets: Res<SpriteSheets>,
+        species: Res<SpeciesRegistry>,
+        time: Res<Time>,
+        mut ev_died: EventWriter<AnimalDiedEvent>,
+        mut commands: Commands,
+    ) {
+        let now = time.seconds_since_startup();
+        // Two hunters can reach the same prey on the same frame, only one of them gets it
+        let mut killed: HashSet<Entity> = HashSet::new();
+
+        for (entity, pos, animal_type, locomotion, stats, perceived, mut hunt, path, task) in
+            query.iter_mut()
+        {
+            let definition = species.get(*animal_type);
+            let hunting = definition.hunting();
+            // Whether the prey is hunting something itself doesn't matter, only what it is
+            let (prey_pos, prey_type, prey_life_stage, prey_state, prey_genome, prey_momentum) =
+                match prey_query.get(hunt.prey) {
+                    Ok(prey) if definition.hunts(*prey.1) => prey,
+                    _ => continue,
+                };
+
+            let prey_visible = perceived
+                .animals
+                .iter()
+                .any(|other| other.entity == hunt.prey);
+            if prey_visible {
+                hunt.last_seen_pos = prey_pos.0;
+                hunt.last_seen_at = now;
+            } else if now - hunt.last_seen_at > LOST_PREY_TIMEOUT {
+                commands
+                    .entity(entity)
+                    .remove::<Hunt>()
+                    .insert(Gait::Walk)
+                    .remove::<Path>()
+                    .remove::<Velocity>();
+                continue;
+            }
+
+            let distance = (prey_pos.0 - pos.0).length();
+            let kill_distance =
+                hunting.kill_distance() * vulnerability(prey_life_stage, prey_state);
+            if prey_visible && distance <= kill_distance && killed.insert(hunt.prey) {
+                ev_died.send(AnimalDiedEvent {
+                    entity: hunt.prey,
+                    cause: DeathCause::Predation,
+                });
+                let carcass = Self::spawn_carcass(
+                    &mut commands,
+                    &sprite_sheets,
+                    &species,
+                    *prey_type,
+                    prey_life_stage,
+                    prey_genome,
+                    prey_pos.0,
+                );
+                commands
+                    .entity(entity)
+                    .remove::<Hunt>()
+                    .insert(Gait::Walk)
+                    .remove::<Path>()
+                    .remove::<Velocity>()
+                    .insert(Feeding(carcass));
+                continue;
+            }
+
+            // Sneak up first, the last stretch is a sprint
+            let gait = if distance > hunting.pounce_distance() {
+                Gait::Sneak
+            } else {
+                Gait::Run
+            };
+            commands.entity(entity).insert(gait);
+
+            hunt.replan_timer.tick(time.delta());
+            if task.is_some() || (path.is_some() && !hunt.replan_timer.just_finished()) {
+                continue;
+            }
+
+            // Aim where the prey is going to be rather than where it is
+            let target = if prey_visible {
+                let lead_time = (distance / stats.run_speed).min(MAX_PURSUIT_LEAD_TIME);
+                prey_pos.0 + prey_momentum.velocity() * lead_time
+            } else {
+                hunt.last_seen_pos
+            };
+            let target = if pathfinder.is_segment_clear(pos.0, target, *locomotion) {
+                target
+            } else if pathfinder.is_segment_clear(pos.0, hunt.last_seen_pos, *locomotion) {
+                hunt.last_seen_pos
+            } else {
+                commands
+                    .entity(entity)
+                    .remove::<Path>()
+                    .remove::<Velocity>()
+                    .insert(PathRequest {
+                        goal: hunt.last_seen_pos,
+                        options: PathOptions {
+                            max_nodes: Some(PURSUIT_SEARCH_BUDGET),
+                            fallback_to_nearest: true,
+                            locomotion: *locomotion,
+                        },
+                    });
+                continue;
+            };
+
+            // Nothing in the way, run straight at it
+            commands
+                .entity(entity)
+                .remove::<Velocity>()
+                .insert(Path(VecDeque::from([target])));
+        }
+    }
+
+    fn spawn_carcass(
+        commands: &mut Commands,
+        sprite_sheets: &SpriteSheets,
+        species: &SpeciesRegistry,
+        animal_type: AnimalType,
+        life_stage: &LifeStage,
+        genome: &Genome,
+        pos: Vec2,
+    ) -> Entity {
+        let size = genome.size * life_stage.size_factor();
+        let atlas = sprite_sheets
+            .animal_atlases(&animal_type)
+            .get(&AnimalState::Idle)
+            .and_then(|direction_map| direction_map.get(&AnimalDirection::Right))
+            .unwrap();
+        let tint = genome.tint_color() * 0.6;
+
+        commands
+            .spawn_bundle(SpriteSheetBundle {
+                sprite: TextureAtlasSprite {
+                    custom_size: Some(
+                        GraphicsPlugin::get_sprite_size(
+                            &AnimalDirection::Right,
+                            &species.get(animal_type).sprite,
+                            life_stage,
+                        ) * genome.size,
+                    ),
+                    color: tint.as_rgba(),
+                    ..default()
+                },
+                texture_atlas: atlas.clone(),
+                transform: Transform {
+                    translation: Vec3::new(pos.x, pos.y, 0.0),
+                    // Lying on its back
+                    rotation: Quat::from_rotation_z(PI),
+                    ..default()
+                },
+                ..default()
+            })
+            .insert_bundle((
+                Carcass {
+                    nutrition: CARCASS_NUTRITION * size,
+                    rot_timer: Timer::from_seconds(CARCASS_ROT_TIME, false),
+                },
+                Pos(pos),
+            ))
+            .id()
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn feed(
+        mut query: Query<(
+            Entity,
+            &Pos,
+            &Locomotion,
+            &Feeding,
+            &Hunger,
+            &mut AnimalState,
+            &mut AnimalDirection,
+            Option<&Path>,
+            Option<&PathfindingTask>,
+        )>,
+        mut carcass_query: Query<(&Pos, &mut Carcass)>,
+        animal_direction_map: Res<AnimalDirectionVectorMap>,
+        mut ev_food_eaten: EventWriter<FoodEatenEvent>,
+        time: Res<Time>,
+        mut commands: Commands,
+    ) {
+        for (entity, pos, locomotion, feeding, hunger, mut state, mut direction, path, task) in
+            query.iter_mut()
+        {
+            let (carcass_pos, mut carcass) = match carcass_query.get_mut(feeding.0) {
+                Ok(carcass) => carcass,
+                Err(_) => continue,
+            };
+
+            if (carcass_pos.0 - pos.0).length() > CARCASS_REACH_DISTANCE {
+                if path.is_none() && task.is_none() {
+                    commands.entity(entity).insert(PathRequest {
+                        goal: carcass_pos.0,
+                        options: PathOptions {
+                            fallback_to_nearest: true,
+                            locomotion: *locomotion,
+                            ..default()
+                        },
+                    });
+                }
+                continue;
+            }
+
+            if *state != AnimalState::Eating {
+                if carcass_pos.0 != pos.0 {
+                    *direction = animal_direction_map.closest_direction(carcass_pos.0 - pos.0);
+                }
+                commands
+                    .entity(entity)
+                    .remove::<Path>()
+                    .remove::<Velocity>();
+                *state = AnimalState::Eating;
+            }
+
+            let bite = (FEED_RATE * time.delta_seconds()).min(carcass.nutrition);
+            carcass.nutrition -= bite;
+            ev_food_eaten.send(FoodEatenEvent {
+                entity,
+                nutrition: bite,
+            });
+
+            let carcass_finished = carcass.nutrition <= 0.0;
+            if carcass_finished {
+                commands.entity(feeding.0).despawn();
+            }
+            if carcass_finished || hunger.0 <= bite {
+                *state = AnimalState::Idle;
+                commands.entity(entity).remove::<Feeding>();
+            }
+        }
+    }
+
+    fn rot_carcasses(
+        mut query: Query<(Entity, &mut Carcass)>,
+        time: Res<Time>,
+        mut commands: Commands,
+    ) {
+        for (entity, mut carcass) in query.iter_mut() {
+            carcass.rot_timer.tick(time.delta());
+            if carcass.rot_timer.finished() {
+                commands.entity(entity).despawn();
+            }
+        }
+    }
+}
+
+fn vulnerability(life_stage: &LifeStage, state: &AnimalState) -> f32 {
+    if *state == AnimalState::Sleeping {
+        life_stage.vulnerability() * SLEEPING_VULNERABILITY
+    } else {
+        life_stage.vulnerability()
+    }
+}
@@ -1,19 +1,59 @@
+mod aging;
 mod animal_behavour;
+mod burrow;
 mod components;
+mod day_cycle;
+mod decision;
+mod disease;
+mod drinking;
+mod eating;
+mod flee;
+mod genetics;
 mod graphics;
+mod herd;
+mod hunting;
 mod map;
+mod memory;
+mod metabolism;
+mod needs;
 mod noise_map_gen;
 mod pathfinder;
+mod perception;
+mod reproduction;
+mod sleep;
+mod species;
+mod steering;
 mod vegetation;
+mod wander;
 
 mod prelude {
+    pub use crate::aging::*;
     pub use crate::animal_behavour::*;
+    pub use crate::burrow::*;
     pub use crate::components::*;
+    pub use crate::day_cycle::*;
+    pub use crate::decision::*;
+    pub use crate::disease::*;
+    pub use crate::drinking::*;
+    pub use crate::eating::*;
+    pub use crate::flee::*;
+    pub use crate::genetics::*;
     pub use crate::graphics::*;
+    pub use crate::herd::*;
+    pub use crate::hunting::*;
     pub use crate::map::*;
+    pub use crate::memory::*;
+    pub use crate::metabolism::*;
+    pub use crate::needs::*;
     pub use crate::noise_map_gen::*;
     pub use crate::pathfinder::*;
+    pub use crate::perception::*;
+    pub use crate::reproduction::*;
+    pub use crate::sleep::*;
+    pub use crate::species::*;
+    pub use crate::steering::*;
     pub use crate::vegetation::*;
+    pub use crate::wander::*;
     pub use bevy::prelude::*;
     pub use bevy::window::PresentMode;
     pub use rand::{thread_rng, Rng};
@@ -29,25 +69,53 @@ const NOISE_MAP_OCTAVES: usize = 4;
 const NOISE_MAP_PERSISTENCE: f64 = 0.5;
 const NOISE_MAP_LACUNARITY: f64 = 2.0;
 
+// Relative to the assets folder
+const SPECIES_DIR: &str = "species";
+// How close to an animal a click has to land to select it
+const SELECTION_RADIUS: f32 = (TILE_SIZE * 4) as f32;
+
 fn main() {
     let test = Vec2::new(10.0, 15.0);
     let test1 = Vec2::new(10.0, 15.0);
 
     println!("{}", test == test1);
+    let species = SpeciesRegistry::load(SPECIES_DIR)
+        .unwrap_or_else(|error| panic!("Couldn't load the species: {}", error));
     let mut map = Map::new();
     for _i in 0..LAKE_COUNT {
         map.generate_lake();
     }
     map.spawn_trees();
-    let pathfinder = Pathfinder::new(map.clone());
+    let pathfinder = Pathfinder::new(map.clone()).with_corner_cutting(CornerCutting::Never);
 
     App::new()
         .add_plugins(DefaultPlugins)
         .add_plugin(GraphicsPlugin)
         .add_plugin(VegetationPlugin)
         .add_plugin(AnimalBehaviourPlugin)
+        .add_plugin(AgingPlugin)
+        .add_plugin(SteeringPlugin)
+        .add_plugin(PathfindingPlugin)
+        .add_plugin(NeedsPlugin)
+        .add_plugin(MetabolismPlugin)
+        .add_plugin(PerceptionPlugin)
+        .add_plugin(MemoryPlugin)
+        .add_plugin(DecisionPlugin)
+        .add_plugin(WanderPlugin)
+        .add_plugin(DrinkingPlugin)
+        .add_plugin(EatingPlugin)
+        .add_plugin(HuntingPlugin)
+        .add_plugin(FleePlugin)
+        .add_plugin(HerdPlugin)
+        .add_plugin(DayCyclePlugin)
+        .add_plugin(BurrowPlugin)
+        .add_plugin(SleepPlugin)
+        .add_plugin(ReproductionPlugin)
+        .add_plugin(GeneticsPlugin)
+        .add_plugin(DiseasePlugin)
         .insert_resource(map)
         .insert_resource(pathfinder)
+        .insert_resource(species)
         .insert_resource(WindowDescriptor {
             title: "Ecosystem sim".to_string(),
             width: 1280.0,
@@ -65,37 +133,56 @@ fn main() {
 
 fn mouse_button_input(
     buttons: Res<Input<MouseButton>>,
-    pathfinder: Res<Pathfinder>,
     mut commands: Commands,
-    mut windows: ResMut<Windows>,
+    windows: Res<Windows>,
     camera_query: Query<&Camera>,
     animal_query: Query<(Entity, &Animal, &Pos)>,
-    mut ev_drawpath: EventWriter<DrawPathEvent>,
+    selected_query: Query<Entity, With<SelectedAnimal>>,
 ) {
-    if buttons.just_pressed(MouseButton::Left) {
-        let window = windows.get_primary_mut().unwrap();
-        if let Some(mouse_pos) = window.cursor_position() {
-            let camera = camera_query.get_single().unwrap();
+    let window = windows.get_primary().unwrap();
+    let map_pos = match cursor_map_pos(window, camera_query.get_single().unwrap()) {
+        Some(map_pos) => map_pos,
+        None => return,
+    };
 
-            let viewport_size = camera.logical_viewport_size().unwrap();
-            let map_offset = (viewport_size / 2.0)
-                - (Vec2::new(
-                    MAP_WIDTH as f32 * (TILE_SIZE as f32 / 2.0),
-                    MAP_HEIGHT as f32 * (TILE_SIZE as f32 / 2.0),
-                ) / Vec2::new(0.5, 0.5));
-            let map_pos = (mouse_pos - map_offset) * Vec2::new(0.5, 0.5);
-
-            let (entity, _, pos) = animal_query.get_single().unwrap();
+    // Left click selects the closest animal so its reasoning gets printed, right click sends the
+    // selected animal somewhere
+    if buttons.just_pressed(MouseButton::Left) {
+        for entity in selected_query.iter() {
+            commands.entity(entity).remove::<SelectedAnimal>();
+        }
 
-            if let Some(path) = pathfinder.a_star(pos.0, map_pos) {
-                ev_drawpath.send(DrawPathEvent(Path(path.clone())));
+        let closest_animal = animal_query
+            .iter()
+            .map(|(entity, _, pos)| (entity, (pos.0 - map_pos).length()))
+            .filter(|(_, distance)| *distance <= SELECTION_RADIUS)
+            .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal));
+        if let Some((entity, _)) = closest_animal {
+            println!("Selected {:?}", entity);
+            commands.entity(entity).insert(SelectedAnimal);
+        }
+    }
 
-                commands.entity(entity).insert(Path(path.clone()));
-            }
+    if buttons.just_pressed(MouseButton::Right) {
+        for entity in selected_query.iter() {
+            let mut request = PathRequest::new(map_pos);
+            request.options.fallback_to_nearest = true;
+            commands.entity(entity).insert(request);
         }
     }
 }
 
+fn cursor_map_pos(window: &Window, camera: &Camera) -> Option<Vec2> {
+    let mouse_pos = window.cursor_position()?;
+    let viewport_size = camera.logical_viewport_size()?;
+    let map_offset = (viewport_size / 2.0)
+        - (Vec2::new(
+            MAP_WIDTH as f32 * (TILE_SIZE as f32 / 2.0),
+            MAP_HEIGHT as f32 * (TILE_SIZE as f32 / 2.0),
+        ) / Vec2::new(0.5, 0.5));
+    Some((mouse_pos - map_offset) * Vec2::new(0.5, 0.5))
+}
+
 fn camera_init(mut commands: Commands) {
     commands.spawn_bundle(Camera2dBundle {
         transform: Transform {
@@ -118,17 +205,23 @@ fn spawn_entities(mut commands: Commands, map: Res<Map>) {
     }
 }
 
-fn spawn_initial_animals(mut commands: Commands) {
-    commands.spawn_bundle((
-        Animal,
-        Pos(Vec2::new(
-            (MAP_WIDTH as f32 / 2.0) * TILE_SIZE as f32,
-            (MAP_HEIGHT as f32 / 2.0) * TILE_SIZE as f32,
-        )),
-        AnimalType::Bunny,
-        AnimalState::Moving,
-        AnimalDirection::Down,
-    ));
+fn spawn_initial_animals(mut commands: Commands, map: Res<Map>, species: Res<SpeciesRegistry>) {
+    let mut rng = thread_rng();
+    for animal_type in species.all() {
+        let definition = species.get(animal_type);
+        let mut spawned = 0;
+        while spawned < definition.initial_count {
+            let tile_idx = rng.gen_range(0..map.size());
+            if !definition.locomotion.can_enter(&map.tiles[tile_idx]) {
+                continue;
+            }
+            commands.spawn_bundle(
+                AnimalBundle::new(&species, animal_type, idx_to_vec2(tile_idx as i32))
+                    .adult(&species),
+            );
+            spawned += 1;
+        }
+    }
 }
 
 fn _render_noise_map(mut commands: Commands) {
@@ -141,9 +234,9 @@ fn _render_noise_map(mut commands: Commands) {
         NOISE_MAP_LACUNARITY,
     );
 
-    for y in 0..noise_map.len() {
-        for x in 0..noise_map[y].len() {
-            let noise_value = noise_map[y][x] as f32;
+    for (y, row) in noise_map.iter().enumerate() {
+        for (x, &noise_value) in row.iter().enumerate() {
+            let noise_value = noise_value as f32;
             commands.spawn_bundle(SpriteBundle {
                 sprite: Sprite {
                     color: Color::Rgba {
@@ -22,8 +22,8 @@ use crate::{
 
 #[derive(Clone, Copy, PartialEq)]
 pub enum TileType {
-    LAND,
-    WATER,
+    Land,
+    Water,
 }
 
 #[derive(Clone)]
@@ -35,20 +35,17 @@ pub struct Tile {
 impl Tile {
     pub fn get_color(&self) -> Color {
         match self.tile_type {
-            TileType::LAND => Color::rgb(0.0, 1.0, 0.0),
-            TileType::WATER => Color::rgb(0.0, 0.0, 1.0),
+            TileType::Land => Color::rgb(0.0, 1.0, 0.0),
+            TileType::Water => Color::rgb(0.0, 0.0, 1.0),
         }
     }
 
     pub fn is_traversable(&self) -> bool {
-        if self.tile_type == TileType::WATER {
-            return false;
-        }
+        self.tile_type == TileType::Land && !self.has_tree()
+    }
 
-        if self.tree_noise_value < TREE_SPAWN_NOISE_TRESHOLD {
-            return false;
-        }
-        return true;
+    pub fn has_tree(&self) -> bool {
+        self.tile_type == TileType::Land && self.tree_noise_value < TREE_SPAWN_NOISE_TRESHOLD
     }
 }
 #[derive(Clone)]
@@ -62,10 +59,10 @@ impl Map {
         Map {
             tiles: vec![
                 Tile {
-                    tile_type: TileType::LAND,
+                    tile_type: TileType::Land,
                     tree_noise_value: 0.0,
                 };
-                MAP_WIDTH as usize * MAP_HEIGHT as usize
+                MAP_WIDTH * MAP_HEIGHT
             ],
             tree_positions: vec![],
         }
@@ -97,7 +94,7 @@ impl Map {
         }
 
         for tile_idx in river_tiles {
-            self.tiles[tile_idx as usize].tile_type = TileType::WATER;
+            self.tiles[tile_idx].tile_type = TileType::Water;
         }
     }
 
@@ -118,6 +115,43 @@ impl Map {
             && point.y >= 0.0
     }
 
+    pub fn tile_at(&self, point: Vec2) -> Option<&Tile> {
+        if !self.in_bounds(point) {
+            return None;
+        }
+        Some(&self.tiles[vec2_to_idx(point)])
+    }
+
+    pub fn is_next_to_water(&self, idx: usize) -> bool {
+        self.has_neighbour_of_type(idx, TileType::Water)
+    }
+
+    pub fn is_next_to_land(&self, idx: usize) -> bool {
+        self.has_neighbour_of_type(idx, TileType::Land)
+    }
+
+    fn has_neighbour_of_type(&self, idx: usize, tile_type: TileType) -> bool {
+        let x = (idx % MAP_WIDTH) as i32;
+        let y = (idx / MAP_WIDTH) as i32;
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                let (nx, ny) = (x + dx, y + dy);
+                if (dx, dy) == (0, 0)
+                    || nx < 0
+                    || ny < 0
+                    || nx >= MAP_WIDTH as i32
+                    || ny >= MAP_HEIGHT as i32
+                {
+                    continue;
+                }
+                if self.tiles[ny as usize * MAP_WIDTH + nx as usize].tile_type == tile_type {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
     pub fn spawn_trees(&mut self) {
         let noise_map = generate_noise_map(
             MAP_WIDTH,
@@ -129,24 +163,23 @@ impl Map {
         );
         let mut tile_tree_map: HashMap<String, bool> = HashMap::new();
 
-        for y in 0..noise_map.len() {
-            for x in 0..noise_map[y].len() {
-                let noise_value = noise_map[y][x];
+        for (y, row) in noise_map.iter().enumerate() {
+            for (x, &noise_value) in row.iter().enumerate() {
                 let tile_idx =
                     vec2_to_idx(Vec2::new((x * TILE_SIZE) as f32, (y * TILE_SIZE) as f32));
-                let mut tile = &mut self.tiles[tile_idx];
+                let tile = &mut self.tiles[tile_idx];
                 tile.tree_noise_value = noise_value;
 
                 let should_spawn =
-                    noise_value < TREE_SPAWN_NOISE_TRESHOLD && tile.tile_type == TileType::LAND;
+                    noise_value < TREE_SPAWN_NOISE_TRESHOLD && tile.tile_type == TileType::Land;
                 if should_spawn {
                     let key = format!("{}-{}", x as i32, y as i32);
 
-                    if tile.tile_type == TileType::WATER {
+                    if tile.tile_type == TileType::Water {
                         println!("Spawning tree in water");
                     }
 
-                    if let None = tile_tree_map.get(&key) {
+                    if tile_tree_map.get(&key).is_none() {
                         self.tree_positions.push(Vec2::new(x as f32, y as f32));
                         tile_tree_map.insert(key, true);
                     }
@@ -216,5 +249,5 @@ pub fn idx_to_vec2(idx: i32) -> Vec2 {
 
 pub fn vec2_to_idx(point: Vec2) -> usize {
     let scaled_point = point * 1.0 / TILE_SIZE as f32;
-    scaled_point.y as usize * MAP_WIDTH as usize + scaled_point.x as usize
+    scaled_point.y as usize * MAP_WIDTH + scaled_point.x as usize
 }
@@ -0,0 +1,242 @@
+use crate::prelude::*;
+
+// Memories this faded are dropped
+const FORGET_CONFIDENCE: f32 = 0.1;
+// Sightings closer than this to a remembered place refresh it instead of taking up a new slot
+const SAME_PLACE_DISTANCE: f32 = (TILE_SIZE * 3) as f32;
+// Close enough to tell whether remembered food is still there
+const VERIFY_DISTANCE: f32 = (TILE_SIZE * 2) as f32;
+// A place this far away is worth half as much as one right next to the animal
+const RECALL_DISTANCE_SCALE: f32 = (TILE_SIZE * 20) as f32;
+// A path that ends further away than this from a remembered place doesn't get there
+const REACHED_DISTANCE: f32 = TILE_SIZE as f32 * 1.5;
+
+pub struct MemoryPlugin;
+
+impl Plugin for MemoryPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(Self::memorise)
+            .add_system(Self::fade_memories)
+            .add_system(Self::forget_unreachable);
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemoryKind {
+    Water,
+    // Plants for herbivores, carcasses for carnivores
+    Food,
+    Predator,
+    Mate,
+}
+
+impl MemoryKind {
+    // Seconds for the confidence in a memory to halve. Water stays where it is, predators don't
+    fn half_life(&self) -> f64 {
+        match self {
+            MemoryKind::Water => 300.0,
+            MemoryKind::Food => 60.0,
+            MemoryKind::Predator => 20.0,
+            MemoryKind::Mate => 30.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Remembered {
+    pub kind: MemoryKind,
+    pub pos: Vec2,
+    pub entity: Option<Entity>,
+    pub seen_at: f64,
+    // 1 when just seen, fades towards 0
+    pub confidence: f32,
+}
+
+// The remembered place the animal asked for a path to
+#[derive(Component)]
+pub struct RememberedGoal {
+    pub kind: MemoryKind,
+    pub pos: Vec2,
+}
+
+// Places the animal has seen things at, the least certain ones make room when it's full
+#[derive(Component)]
+pub struct Memory {
+    entries: Vec<Remembered>,
+    capacity: usize,
+}
+
+impl Memory {
+    pub fn new(capacity: usize) -> Self {
+        Memory {
+            entries: Vec::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn remember(&mut self, kind: MemoryKind, pos: Vec2, entity: Option<Entity>, now: f64) {
+        let sighting = Remembered {
+            kind,
+            pos,
+            entity,
+            seen_at: now,
+            confidence: 1.0,
+        };
+        let existing = self.entries.iter_mut().find(|remembered| {
+            remembered.kind == kind
+                && match (remembered.entity, entity) {
+                    (Some(remembered_entity), Some(entity)) => remembered_entity == entity,
+                    _ => (remembered.pos - pos).length() <= SAME_PLACE_DISTANCE,
+                }
+        });
+        if let Some(existing) = existing {
+            *existing = sighting;
+        } else if self.entries.len() < self.capacity {
+            self.entries.push(sighting);
+        } else if let Some(weakest) = self
+            .entries
+            .iter_mut()
+            .min_by(|a, b| a.confidence.partial_cmp(&b.confidence).unwrap())
+        {
+            *weakest = sighting;
+        }
+    }
+
+    pub fn recall(&self, kind: MemoryKind) -> impl Iterator<Item = &Remembered> {
+        self.entries
+            .iter()
+            .filter(move |remembered| remembered.kind == kind)
+    }
+
+    pub fn recall_entity(&self, kind: MemoryKind, entity: Entity) -> Option<&Remembered> {
+        self.recall(kind)
+            .find(|remembered| remembered.entity == Some(entity))
+    }
+
+    // The place most worth going to, a sure memory further away can beat a faded one close by
+    pub fn best(&self, kind: MemoryKind, from: Vec2) -> Option<&Remembered> {
+        let value = |remembered: &Remembered| {
+            remembered.confidence / (1.0 + (remembered.pos - from).length() / RECALL_DISTANCE_SCALE)
+        };
+        self.recall(kind)
+            .max_by(|a, b| value(a).partial_cmp(&value(b)).unwrap())
+    }
+
+    pub fn forget(&mut self, kind: MemoryKind, pos: Vec2) {
+        self.entries
+            .retain(|remembered| remembered.kind != kind || remembered.pos != pos);
+    }
+}
+
+impl MemoryPlugin {
+    // Only the closest water and food get remembered each tick, otherwise a single look at a lake
+    // or a meadow would fill up the memory
+    #[allow(clippy::type_complexity)]
+    fn memorise(
+        mut query: Query<
+            (
+                &Pos,
+                &AnimalType,
+                &Diet,
+                &Perceived,
+                &mut Memory,
+                Option<&Mate>,
+            ),
+            // Can't see anything from down there, and not seeing it doesn't mean it's gone
+            Without<InBurrow>,
+        >,
+        species: Res<SpeciesRegistry>,
+        time: Res<Time>,
+    ) {
+        let now = time.seconds_since_startup();
+        let closest = |pos: Vec2, candidates: &mut dyn Iterator<Item = (Option<Entity>, Vec2)>| {
+            candidates.min_by(|(_, a), (_, b)| {
+                (*a - pos)
+                    .length()
+                    .partial_cmp(&(*b - pos).length())
+                    .unwrap()
+            })
+        };
+
+        for (pos, animal_type, diet, perceived, mut memory, mate) in query.iter_mut() {
+            let mut water = perceived.water.iter().map(|water_pos| (None, *water_pos));
+            if let Some((_, water_pos)) = closest(pos.0, &mut water) {
+                memory.remember(MemoryKind::Water, water_pos, None, now);
+            }
+
+            let food = match diet {
+                Diet::Herbivore => &perceived.plants,
+                Diet::Carnivore => &perceived.carcasses,
+            };
+            let mut food_sightings = food.iter().map(|(food, food_pos)| (Some(*food), *food_pos));
+            if let Some((food, food_pos)) = closest(pos.0, &mut food_sightings) {
+                memory.remember(MemoryKind::Food, food_pos, food, now);
+            }
+
+            for other in perceived.animals.iter() {
+                if species.get(other.animal_type).hunts(*animal_type) {
+                    memory.remember(MemoryKind::Predator, other.pos, Some(other.entity), now);
+                } else if mate.is_some_and(|mate| mate.partner == other.entity) {
+                    memory.remember(MemoryKind::Mate, other.pos, Some(other.entity), now);
+                }
+            }
+
+            // Food that should be in plain sight but isn't has been eaten by someone else
+            let gone: Vec<Vec2> = memory
+                .recall(MemoryKind::Food)
+                .filter(|remembered| {
+                    (remembered.pos - pos.0).length() <= VERIFY_DISTANCE
+                        && !food
+                            .iter()
+                            .any(|(food, _)| remembered.entity == Some(*food))
+                })
+                .map(|remembered| remembered.pos)
+                .collect();
+            for food_pos in gone {
+                memory.forget(MemoryKind::Food, food_pos);
+            }
+        }
+    }
+
+    fn fade_memories(mut query: Query<&mut Memory>, time: Res<Time>) {
+        let now = time.seconds_since_startup();
+        for mut memory in query.iter_mut() {
+            for remembered in memory.entries.iter_mut() {
+                let age = now - remembered.seen_at;
+                remembered.confidence = 0.5f64.powf(age / remembered.kind.half_life()) as f32;
+            }
+            memory
+                .entries
+                .retain(|remembered| remembered.confidence >= FORGET_CONFIDENCE);
+        }
+    }
+
+    // Remembered places the animal can't get to are forgotten. Those paths fall back to the
+    // nearest tile, so otherwise the animal would keep walking to the same dead end.
+    #[allow(clippy::type_complexity)]
+    fn forget_unreachable(
+        mut query: Query<(
+            Entity,
+            &RememberedGoal,
+            &mut Memory,
+            Option<&PathFailed>,
+            Option<(&PathOutcome, ChangeTrackers<PathOutcome>)>,
+        )>,
+        mut commands: Commands,
+    ) {
+        for (entity, goal, mut memory, path_failed, outcome) in query.iter_mut() {
+            let reached = match (path_failed, outcome) {
+                (Some(_), _) => false,
+                (None, Some((outcome, tracker))) if tracker.is_changed() => {
+                    (outcome.end - goal.pos).length() <= REACHED_DISTANCE
+                }
+                // Still looking for a path
+                _ => continue,
+            };
+            if !reached {
+                memory.forget(goal.kind, goal.pos);
+            }
+            commands.entity(entity).remove::<RememberedGoal>();
+        }
+    }
+}
@@ -0,0 +1,120 @@
+use crate::prelude::*;
+
+// Energy an animal can store per unit of body mass
+const ENERGY_PER_MASS: f32 = 100.0;
+// Per second, for an animal of mass 1. Basal metabolism follows Kleiber's law, mass^0.75
+const BASAL_COST: f32 = 0.6;
+// Per second, multiplied by mass and the square of the speed in world units per second
+const MOVEMENT_COST: f32 = 0.002;
+// Per second while the animal is still growing, multiplied by mass
+const GROWTH_COST: f32 = 0.3;
+// Per second and per young being carried
+const PREGNANCY_COST: f32 = 0.15;
+
+pub struct MetabolismPlugin;
+
+impl Plugin for MetabolismPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(Self::metabolise)
+            .add_system(Self::digest_food);
+    }
+}
+
+#[derive(Component)]
+pub struct Energy {
+    pub current: f32,
+    // Grows with the animal
+    pub capacity: f32,
+}
+
+impl Energy {
+    pub fn full(capacity: f32) -> Self {
+        Energy {
+            current: capacity,
+            capacity,
+        }
+    }
+
+    pub fn fraction(&self) -> f32 {
+        (self.current / self.capacity).clamp(0.0, 1.0)
+    }
+}
+
+pub fn body_mass(species: &SpeciesDefinition, genome: &Genome, life_stage: LifeStage) -> f32 {
+    species.body_mass * genome.size * life_stage.size_factor()
+}
+
+pub fn energy_capacity(mass: f32) -> f32 {
+    ENERGY_PER_MASS * mass
+}
+
+impl MetabolismPlugin {
+    #[allow(clippy::type_complexity)]
+    fn metabolise(
+        mut query: Query<(
+            Entity,
+            &AnimalType,
+            &Genome,
+            &LifeStage,
+            &Momentum,
+            &mut Energy,
+            &mut Hunger,
+            &Infection,
+            Option<&Pregnancy>,
+        )>,
+        mut ev_died: EventWriter<AnimalDiedEvent>,
+        species: Res<SpeciesRegistry>,
+        time: Res<Time>,
+    ) {
+        for (
+            entity,
+            animal_type,
+            genome,
+            life_stage,
+            momentum,
+            mut energy,
+            mut hunger,
+            infection,
+            pregnancy,
+        ) in query.iter_mut()
+        {
+            let mass = body_mass(species.get(*animal_type), genome, *life_stage);
+            energy.capacity = energy_capacity(mass);
+
+            let basal = BASAL_COST * mass.powf(0.75) * genome.metabolism;
+            // Drag grows with the square of the speed, sprinting is far more expensive than walking
+            let movement = MOVEMENT_COST * mass * momentum.speed.powi(2);
+            let growth = match life_stage {
+                LifeStage::Newborn | LifeStage::Juvenile => GROWTH_COST * mass,
+                LifeStage::Adult | LifeStage::Elderly => 0.0,
+            };
+            let reproduction = pregnancy.map_or(0.0, |pregnancy| {
+                PREGNANCY_COST * pregnancy.litter_size as f32
+            });
+
+            let illness = infection.energy_cost() * mass;
+
+            energy.current -=
+                (basal + movement + growth + reproduction + illness) * time.delta_seconds();
+            energy.current = energy.current.min(energy.capacity);
+            // Hunger is how empty the energy store is, that's what the decisions look at
+            hunger.0 = NEED_LIMIT * (1.0 - energy.fraction());
+
+            if energy.current <= 0.0 {
+                ev_died.send(AnimalDiedEvent {
+                    entity,
+                    cause: DeathCause::Starvation,
+                });
+            }
+        }
+    }
+
+    // One unit of nutrition is one unit of energy
+    fn digest_food(mut ev_food_eaten: EventReader<FoodEatenEvent>, mut query: Query<&mut Energy>) {
+        for ev in ev_food_eaten.iter() {
+            if let Ok(mut energy) = query.get_mut(ev.entity) {
+                energy.current = (energy.current + ev.nutrition).min(energy.capacity);
+            }
+        }
+    }
+}
@@ -0,0 +1,75 @@
+use crate::prelude::*;
+
+pub const NEED_LIMIT: f32 = 100.0;
+// Per second
+const THIRST_RATE: f32 = 1.0;
+const MOVING_NEED_MULTIPLIER: f32 = 1.75;
+// Sprinting wears the animal out much faster than walking around
+const RUNNING_NEED_MULTIPLIER: f32 = 3.0;
+const FATIGUE_RATE: f32 = 0.5;
+const SLEEP_RECOVERY_RATE: f32 = 2.5;
+
+pub struct NeedsPlugin;
+
+impl Plugin for NeedsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<FoodEatenEvent>()
+            .add_system(Self::increase_needs);
+    }
+}
+
+// Follows the animal's energy store, see MetabolismPlugin
+#[derive(Component, Default)]
+pub struct Hunger(pub f32);
+#[derive(Component, Default)]
+pub struct Thirst(pub f32);
+// Builds up while awake and goes away with sleep, see SleepPlugin
+#[derive(Component, Default)]
+pub struct Fatigue(pub f32);
+
+pub struct FoodEatenEvent {
+    pub entity: Entity,
+    pub nutrition: f32,
+}
+
+impl NeedsPlugin {
+    #[allow(clippy::type_complexity)]
+    fn increase_needs(
+        mut query: Query<(
+            Entity,
+            &Animal,
+            &AnimalState,
+            &Genome,
+            &mut Thirst,
+            &mut Fatigue,
+            &Gait,
+        )>,
+        mut ev_died: EventWriter<AnimalDiedEvent>,
+        time: Res<Time>,
+    ) {
+        for (entity, _, state, genome, mut thirst, mut fatigue, gait) in query.iter_mut() {
+            let activity_multiplier = if *state == AnimalState::Moving && *gait == Gait::Run {
+                RUNNING_NEED_MULTIPLIER
+            } else if *state == AnimalState::Moving {
+                MOVING_NEED_MULTIPLIER
+            } else {
+                1.0
+            };
+            let multiplier = activity_multiplier * genome.metabolism;
+            thirst.0 += THIRST_RATE * multiplier * time.delta_seconds();
+            fatigue.0 = if *state == AnimalState::Sleeping {
+                (fatigue.0 - SLEEP_RECOVERY_RATE * time.delta_seconds()).max(0.0)
+            } else {
+                (fatigue.0 + FATIGUE_RATE * activity_multiplier * time.delta_seconds())
+                    .min(NEED_LIMIT)
+            };
+
+            if thirst.0 >= NEED_LIMIT {
+                ev_died.send(AnimalDiedEvent {
+                    entity,
+                    cause: DeathCause::Dehydration,
+                });
+            }
+        }
+    }
+}
@@ -17,8 +17,8 @@ pub fn generate_noise_map(
     let mut perlin_min = f64::MAX;
     let mut perlin_max = f64::MIN;
 
-    for y in 0..map_height {
-        for x in 0..map_width {
+    for (y, row) in noise_map.iter_mut().enumerate() {
+        for (x, noise) in row.iter_mut().enumerate() {
             let mut amplitude = 1.0;
             let mut frequency = 1.0;
             let mut noise_height = 0.0;
@@ -39,13 +39,13 @@ pub fn generate_noise_map(
                     perlin_max = noise_height;
                 }
             }
-            noise_map[y][x] = noise_height;
+            *noise = noise_height;
         }
     }
 
-    for y in 0..map_height {
-        for x in 0..map_width {
-            noise_map[y][x] = inv_lerp(perlin_min, perlin_max, noise_map[y][x]);
+    for row in noise_map.iter_mut() {
+        for noise in row.iter_mut() {
+            *noise = inv_lerp(perlin_min, perlin_max, *noise);
         }
     }
     println!("Perlin range: {} - {}", perlin_min, perlin_max);
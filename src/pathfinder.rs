@@ -1,169 +1,890 @@
-use std::collections::VecDeque;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashSet, VecDeque};
+use std::sync::Arc;
+
+use bevy::ecs::system::EntityCommands;
+use bevy::tasks::{AsyncComputeTaskPool, Task};
+use bevy::utils::HashMap;
+use futures_lite::future;
 
 use crate::prelude::*;
 
-pub struct Pathfinder {
-    map: Map,
+// How many searches can be handed to the task pool in a single frame, the rest wait for the next one
+const MAX_PATH_SEARCHES_PER_FRAME: usize = 8;
+const PATH_SEARCH_TIMEOUT: f64 = 2.0;
+const PATH_SEARCH_STALE_DISTANCE: f32 = (TILE_SIZE * 3) as f32;
+
+pub struct PathfindingPlugin;
+
+impl Plugin for PathfindingPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(Self::dispatch_path_requests)
+            .add_system(Self::poll_path_tasks);
+    }
+}
+
+#[derive(Component)]
+pub struct PathfindingTask {
+    task: Task<Result<PathResult, PathError>>,
+    start: Vec2,
+    search: PathSearch,
+    started_at: f64,
+}
+
+// What a task is searching for, kept around so the search can be started again
+#[derive(Clone)]
+enum PathSearch {
+    To(PathRequest),
+    Nearest(NearestPathRequest),
+}
+
+impl PathSearch {
+    fn options_mut(&mut self) -> &mut PathOptions {
+        match self {
+            PathSearch::To(request) => &mut request.options,
+            PathSearch::Nearest(request) => &mut request.options,
+        }
+    }
+
+    // Searches for the nearest of several goals report their failures at the start
+    fn goal(&self, start: Vec2) -> Vec2 {
+        match self {
+            PathSearch::To(request) => request.goal,
+            PathSearch::Nearest(_) => start,
+        }
+    }
+
+    fn run(&self, pathfinder: &Pathfinder, start: Vec2) -> Result<PathResult, PathError> {
+        match self {
+            PathSearch::To(request) => pathfinder.a_star(start, request.goal, &request.options),
+            PathSearch::Nearest(request) => {
+                pathfinder.nearest_tile(start, &request.goals, request.max_cost, &request.options)
+            }
+        }
+    }
+
+    // The same search to the next fallback goal, if there's one left
+    fn next_fallback(&self, fallback_goals: Option<&mut FallbackGoals>) -> Option<PathRequest> {
+        match self {
+            PathSearch::To(request) => fallback_goals
+                .and_then(|fallback_goals| fallback_goals.0.pop_front())
+                .map(|goal| PathRequest {
+                    goal,
+                    options: request.options,
+                }),
+            PathSearch::Nearest(_) => None,
+        }
+    }
+
+    fn request_again(self, commands: &mut EntityCommands) {
+        match self {
+            PathSearch::To(request) => commands.insert(request),
+            PathSearch::Nearest(request) => commands.insert(request),
+        };
+    }
+}
+
+impl PathfindingPlugin {
+    fn dispatch_path_requests(
+        query: Query<(Entity, &PathRequest, &Pos, Option<&Locomotion>)>,
+        nearest_query: Query<(Entity, &NearestPathRequest, &Pos, Option<&Locomotion>)>,
+        pathfinder: Res<Pathfinder>,
+        time: Res<Time>,
+        mut commands: Commands,
+    ) {
+        let task_pool = AsyncComputeTaskPool::get();
+        let searches = query
+            .iter()
+            .map(|(entity, request, pos, locomotion)| {
+                (entity, PathSearch::To(*request), pos, locomotion)
+            })
+            .chain(
+                nearest_query
+                    .iter()
+                    .map(|(entity, request, pos, locomotion)| {
+                        (
+                            entity,
+                            PathSearch::Nearest(request.clone()),
+                            pos,
+                            locomotion,
+                        )
+                    }),
+            )
+            .take(MAX_PATH_SEARCHES_PER_FRAME);
+        for (entity, mut search, pos, locomotion) in searches {
+            search.options_mut().locomotion = locomotion.copied().unwrap_or_default();
+            let pathfinder = pathfinder.clone();
+            let start = pos.0;
+            let task_search = search.clone();
+            let task = task_pool.spawn(async move { task_search.run(&pathfinder, start) });
+
+            // Inserting a new task drops the previous one, which cancels it
+            commands
+                .entity(entity)
+                .remove::<PathRequest>()
+                .remove::<NearestPathRequest>()
+                .remove::<PathFailed>()
+                .insert(PathfindingTask {
+                    task,
+                    start,
+                    search,
+                    started_at: time.seconds_since_startup(),
+                });
+        }
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn poll_path_tasks(
+        mut query: Query<(
+            Entity,
+            &mut PathfindingTask,
+            &Pos,
+            Option<&mut FallbackGoals>,
+            Option<&SelectedAnimal>,
+        )>,
+        time: Res<Time>,
+        mut commands: Commands,
+        mut ev_drawpath: EventWriter<DrawPathEvent>,
+    ) {
+        for (entity, mut path_task, pos, mut fallback_goals, selected) in query.iter_mut() {
+            if (pos.0 - path_task.start).length() > PATH_SEARCH_STALE_DISTANCE {
+                // The animal has moved too far away from where the search started, so plan again
+                let mut entity_commands = commands.entity(entity);
+                entity_commands.remove::<PathfindingTask>();
+                path_task.search.clone().request_again(&mut entity_commands);
+                continue;
+            }
+            // A search that finished on the frame it ran out of time still counts
+            let result = match future::block_on(future::poll_once(&mut path_task.task)) {
+                Some(result) => result,
+                None if time.seconds_since_startup() - path_task.started_at
+                    > PATH_SEARCH_TIMEOUT =>
+                {
+                    Err(PathError::TimedOut)
+                }
+                None => continue,
+            };
+
+            let mut entity_commands = commands.entity(entity);
+            entity_commands.remove::<PathfindingTask>();
+            match result {
+                Ok(result) => {
+                    if selected.is_some() {
+                        println!(
+                            "{:?}: path to {} costs {:.1} tiles, {} nodes expanded{}",
+                            entity,
+                            result.end,
+                            result.cost,
+                            result.nodes_expanded,
+                            if result.reached_goal {
+                                ""
+                            } else {
+                                ", goal out of reach"
+                            }
+                        );
+                    }
+                    ev_drawpath.send(DrawPathEvent(Path(result.path.clone())));
+                    // Without a velocity the animal heads for the first step of the new path
+                    // straight away, instead of finishing the step it was on
+                    entity_commands
+                        .remove::<FallbackGoals>()
+                        .remove::<Velocity>()
+                        .insert(Path(result.path))
+                        .insert(PathOutcome { end: result.end });
+                }
+                // Moves on to the next fallback goal, and only gives up once there's none left
+                Err(error) => match path_task
+                    .search
+                    .next_fallback(fallback_goals.as_deref_mut())
+                {
+                    Some(request) => {
+                        entity_commands.insert(request);
+                    }
+                    None => {
+                        entity_commands
+                            .remove::<FallbackGoals>()
+                            .insert(PathFailed {
+                                goal: path_task.search.goal(path_task.start),
+                                error,
+                            });
+                    }
+                },
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PathError {
+    OutOfBounds,
+    GoalBlocked,
+    Unreachable,
+    BudgetExceeded,
+    // The search took too long in wall-clock time, see PATH_SEARCH_TIMEOUT
+    TimedOut,
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct PathOptions {
+    // Maximum amount of nodes the search is allowed to expand, None means no limit
+    pub max_nodes: Option<usize>,
+    // When the goal can't be reached, return a path to the closest tile to it instead of an error
+    pub fallback_to_nearest: bool,
+    pub locomotion: Locomotion,
 }
 
 #[derive(Clone)]
-struct Node {
-    g_cost: f32,
+pub struct PathResult {
+    pub path: VecDeque<Vec2>,
+    // Tile the path ends on, which is where the animal already is when the path is empty
+    pub end: Vec2,
+    pub cost: f32,
+    pub nodes_expanded: usize,
+    // false when the search fell back to the nearest reachable tile
+    pub reached_goal: bool,
+}
+
+// Whether a diagonal step may squeeze past the corners of the two orthogonal tiles it touches.
+// The game sticks to Never, the other policies are there to experiment with.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum CornerCutting {
+    #[default]
+    Never,
+    OneSideOpen,
+    Always,
+}
+
+impl CornerCutting {
+    fn allows(&self, first_side_open: bool, second_side_open: bool) -> bool {
+        match self {
+            CornerCutting::Never => first_side_open && second_side_open,
+            CornerCutting::OneSideOpen => first_side_open || second_side_open,
+            CornerCutting::Always => true,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Pathfinder {
+    map: Arc<Map>,
+    corner_cutting: CornerCutting,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+struct OpenNode {
+    f_cost: f32,
     h_cost: f32,
-    parent_node: Option<Box<Node>>,
-    pos: Vec2,
+    idx: usize,
 }
 
-impl Node {
-    pub fn f_cost(&self) -> f32 {
-        self.g_cost + self.h_cost
+impl Eq for OpenNode {}
+
+// Reversed so that BinaryHeap pops the node with the lowest f cost (and lowest h cost on ties) first
+impl Ord for OpenNode {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .f_cost
+            .partial_cmp(&self.f_cost)
+            .unwrap_or(Ordering::Equal)
+            .then_with(|| {
+                other
+                    .h_cost
+                    .partial_cmp(&self.h_cost)
+                    .unwrap_or(Ordering::Equal)
+            })
     }
 }
 
+impl PartialOrd for OpenNode {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+struct SearchState {
+    g_costs: HashMap<usize, f32>,
+    parents: HashMap<usize, usize>,
+    nodes_expanded: usize,
+}
+
+enum SearchOutcome {
+    Found(usize),
+    Exhausted {
+        closest_idx: usize,
+        budget_exceeded: bool,
+    },
+}
+
 impl Pathfinder {
     pub fn new(map: Map) -> Self {
-        Pathfinder { map }
+        Pathfinder {
+            map: Arc::new(map),
+            corner_cutting: CornerCutting::default(),
+        }
     }
 
-    pub fn a_star(&self, start: Vec2, end: Vec2) -> Option<VecDeque<Vec2>> {
-        let end_tile = idx_to_vec2(vec2_to_idx(end) as i32);
-        let start_tile = idx_to_vec2(vec2_to_idx(start) as i32);
+    pub fn with_corner_cutting(mut self, corner_cutting: CornerCutting) -> Self {
+        self.corner_cutting = corner_cutting;
+        self
+    }
 
-        if !self.map.tiles[vec2_to_idx(end_tile)].is_traversable() {
-            return None;
-        }
-        let destination_vec = end_tile - start_tile;
-        println!(
-            "{}, {} / {}, {}",
-            start_tile.x, start_tile.y, end_tile.x, end_tile.y
-        );
-        let mut path: Vec<Vec2> = Vec::new();
-        let mut open_nodes: Vec<Node> = vec![Node {
-            g_cost: 0.0,
-            h_cost: destination_vec.length().ceil(),
-            parent_node: None,
-            pos: start_tile,
-        }];
-        let mut closed_nodes: Vec<Node> = vec![];
-
-        let mut current_node: Node;
-        while open_nodes.len() > 0 {
-            current_node = open_nodes[0].clone();
-            for node in open_nodes.iter() {
-                if node.pos == current_node.pos {
-                    continue;
-                }
+    // Walks every tile the straight line between two points passes through
+    pub fn is_segment_clear(&self, from: Vec2, to: Vec2, locomotion: Locomotion) -> bool {
+        let from_tile = from / TILE_SIZE as f32;
+        let to_tile = to / TILE_SIZE as f32;
+        let direction = to_tile - from_tile;
+
+        let (mut x, mut y) = (from_tile.x.floor() as i32, from_tile.y.floor() as i32);
+        let (end_x, end_y) = (to_tile.x.floor() as i32, to_tile.y.floor() as i32);
+        let step_x = direction.x.signum() as i32;
+        let step_y = direction.y.signum() as i32;
+
+        let t_delta_x = if direction.x != 0.0 {
+            1.0 / direction.x.abs()
+        } else {
+            f32::MAX
+        };
+        let t_delta_y = if direction.y != 0.0 {
+            1.0 / direction.y.abs()
+        } else {
+            f32::MAX
+        };
+        let mut t_max_x = if direction.x > 0.0 {
+            ((x + 1) as f32 - from_tile.x) * t_delta_x
+        } else if direction.x < 0.0 {
+            (from_tile.x - x as f32) * t_delta_x
+        } else {
+            f32::MAX
+        };
+        let mut t_max_y = if direction.y > 0.0 {
+            ((y + 1) as f32 - from_tile.y) * t_delta_y
+        } else if direction.y < 0.0 {
+            (from_tile.y - y as f32) * t_delta_y
+        } else {
+            f32::MAX
+        };
+
+        let max_steps = (end_x - x).abs() + (end_y - y).abs();
+        for _ in 0..max_steps {
+            if (x, y) == (end_x, end_y) {
+                break;
+            }
 
-                if node.f_cost() < current_node.f_cost()
-                    || node.f_cost() == current_node.f_cost() && node.h_cost < current_node.h_cost
+            if (t_max_x - t_max_y).abs() < f32::EPSILON {
+                // Passing exactly through a corner, same rules as a diagonal step in the search
+                let first_side_open = self.can_enter_tile(x + step_x, y, locomotion);
+                let second_side_open = self.can_enter_tile(x, y + step_y, locomotion);
+                if !self
+                    .corner_cutting
+                    .allows(first_side_open, second_side_open)
                 {
-                    current_node = node.clone();
+                    return false;
                 }
+                x += step_x;
+                y += step_y;
+                t_max_x += t_delta_x;
+                t_max_y += t_delta_y;
+            } else if t_max_x < t_max_y {
+                x += step_x;
+                t_max_x += t_delta_x;
+            } else {
+                y += step_y;
+                t_max_y += t_delta_y;
             }
 
-            // println!("{}, {}", current_node.pos.x, current_node.pos.y);
-            closed_nodes.push(current_node.clone());
+            if !self.can_enter_tile(x, y, locomotion) {
+                return false;
+            }
+        }
 
-            let mut idx_to_remove: Option<usize> = None;
-            for (idx, node) in open_nodes.iter().enumerate() {
-                if node.pos == current_node.pos {
-                    idx_to_remove = Some(idx);
-                }
+        true
+    }
+
+    fn can_enter_tile(&self, x: i32, y: i32, locomotion: Locomotion) -> bool {
+        if x < 0 || y < 0 || x >= MAP_WIDTH as i32 || y >= MAP_HEIGHT as i32 {
+            return false;
+        }
+        locomotion.can_enter(&self.map.tiles[y as usize * MAP_WIDTH + x as usize])
+    }
+
+    pub fn a_star(
+        &self,
+        start: Vec2,
+        end: Vec2,
+        options: &PathOptions,
+    ) -> Result<PathResult, PathError> {
+        if !self.map.in_bounds(start) || !self.map.in_bounds(end) {
+            return Err(PathError::OutOfBounds);
+        }
+
+        let start_idx = vec2_to_idx(start);
+        let end_idx = vec2_to_idx(end);
+        let goal_blocked = !options.locomotion.can_enter(&self.map.tiles[end_idx]);
+        if goal_blocked && !options.fallback_to_nearest {
+            return Err(PathError::GoalBlocked);
+        }
+
+        let (state, outcome) = self.search(
+            start_idx,
+            options,
+            None,
+            |idx| idx == end_idx,
+            |idx| octile_distance(idx, end_idx),
+        );
+        match outcome {
+            SearchOutcome::Found(idx) => Ok(self.build_result(&state, idx, true)),
+            SearchOutcome::Exhausted {
+                closest_idx,
+                budget_exceeded,
+            } => {
+                let error = if budget_exceeded {
+                    PathError::BudgetExceeded
+                } else if goal_blocked {
+                    PathError::GoalBlocked
+                } else {
+                    PathError::Unreachable
+                };
+                self.fail_or_fallback(&state, closest_idx, error, options)
             }
-            open_nodes.remove(idx_to_remove.unwrap());
+        }
+    }
 
-            if current_node.pos.x == end_tile.x && current_node.pos.y == end_tile.y {
-                // Found exit
-                path = self.retrace_path(current_node);
-                path.reverse();
-                break;
+    // Dijkstra search that stops at the first tile matching is_goal, max_cost is in tiles.
+    // Much cheaper than running a_star for every candidate, and unlike straight-line distance
+    // it knows about lakes and forests in the way.
+    pub fn nearest_where(
+        &self,
+        start: Vec2,
+        max_cost: f32,
+        options: &PathOptions,
+        is_goal: impl Fn(usize) -> bool,
+    ) -> Result<PathResult, PathError> {
+        if !self.map.in_bounds(start) {
+            return Err(PathError::OutOfBounds);
+        }
+
+        let (state, outcome) =
+            self.search(vec2_to_idx(start), options, Some(max_cost), is_goal, |_| {
+                0.0
+            });
+        match outcome {
+            SearchOutcome::Found(idx) => Ok(self.build_result(&state, idx, true)),
+            SearchOutcome::Exhausted {
+                budget_exceeded: true,
+                ..
+            } => Err(PathError::BudgetExceeded),
+            SearchOutcome::Exhausted { .. } => Err(PathError::Unreachable),
+        }
+    }
+
+    pub fn nearest_tile(
+        &self,
+        start: Vec2,
+        goals: &[Vec2],
+        max_cost: f32,
+        options: &PathOptions,
+    ) -> Result<PathResult, PathError> {
+        let goal_tiles: HashSet<usize> = goals
+            .iter()
+            .filter(|goal| self.map.in_bounds(**goal))
+            .map(|goal| vec2_to_idx(*goal))
+            .collect();
+        self.nearest_where(start, max_cost, options, |idx| goal_tiles.contains(&idx))
+    }
+
+    fn search(
+        &self,
+        start_idx: usize,
+        options: &PathOptions,
+        max_cost: Option<f32>,
+        is_goal: impl Fn(usize) -> bool,
+        heuristic: impl Fn(usize) -> f32,
+    ) -> (SearchState, SearchOutcome) {
+        let mut state = SearchState {
+            g_costs: HashMap::new(),
+            parents: HashMap::new(),
+            nodes_expanded: 0,
+        };
+        state.g_costs.insert(start_idx, 0.0);
+
+        let start_h_cost = heuristic(start_idx);
+        let mut open_nodes = BinaryHeap::new();
+        open_nodes.push(OpenNode {
+            f_cost: start_h_cost,
+            h_cost: start_h_cost,
+            idx: start_idx,
+        });
+        let mut closed_nodes: HashSet<usize> = HashSet::new();
+        let mut closest_node = (start_idx, start_h_cost);
+
+        while let Some(current_node) = open_nodes.pop() {
+            if !closed_nodes.insert(current_node.idx) {
+                continue;
             }
-            let mut neighbours =
-                self.evaluate_traversable_node_neighbours(&current_node, start_tile, end_tile);
-            for n in neighbours.iter_mut() {
-                if vec_contains_node(&closed_nodes, n) {
-                    continue;
+            state.nodes_expanded += 1;
+
+            if is_goal(current_node.idx) {
+                return (state, SearchOutcome::Found(current_node.idx));
+            }
+
+            if current_node.h_cost < closest_node.1 {
+                closest_node = (current_node.idx, current_node.h_cost);
+            }
+
+            if let Some(max_nodes) = options.max_nodes {
+                if state.nodes_expanded >= max_nodes {
+                    return (
+                        state,
+                        SearchOutcome::Exhausted {
+                            closest_idx: closest_node.0,
+                            budget_exceeded: true,
+                        },
+                    );
                 }
+            }
 
-                let current_to_neighbour_vec = n.pos - current_node.pos;
-                let new_cost_to_neighbour =
-                    current_node.g_cost + current_to_neighbour_vec.length().ceil();
-                if new_cost_to_neighbour < n.g_cost || !vec_contains_node(&open_nodes, n) {
-                    n.g_cost = new_cost_to_neighbour.ceil();
-                    n.h_cost = (end_tile - n.pos).length().ceil();
+            let current_g_cost = state.g_costs[&current_node.idx];
+            for (neighbour_idx, step_cost) in
+                self.evaluate_traversable_node_neighbours(current_node.idx, options)
+            {
+                if closed_nodes.contains(&neighbour_idx) {
+                    continue;
+                }
 
-                    n.parent_node = Some(Box::new(current_node.clone()));
+                let new_cost_to_neighbour = current_g_cost + step_cost;
+                if max_cost.is_some_and(|max_cost| new_cost_to_neighbour > max_cost) {
+                    continue;
+                }
+                let known_cost = state
+                    .g_costs
+                    .get(&neighbour_idx)
+                    .copied()
+                    .unwrap_or(f32::MAX);
+                if new_cost_to_neighbour < known_cost {
+                    state.g_costs.insert(neighbour_idx, new_cost_to_neighbour);
+                    state.parents.insert(neighbour_idx, current_node.idx);
 
-                    if !vec_contains_node(&open_nodes, n) {
-                        open_nodes.push(n.clone());
-                    }
+                    let h_cost = heuristic(neighbour_idx);
+                    open_nodes.push(OpenNode {
+                        f_cost: new_cost_to_neighbour + h_cost,
+                        h_cost,
+                        idx: neighbour_idx,
+                    });
                 }
             }
         }
 
-        Some(VecDeque::from(path))
+        (
+            state,
+            SearchOutcome::Exhausted {
+                closest_idx: closest_node.0,
+                budget_exceeded: false,
+            },
+        )
     }
 
-    fn retrace_path(&self, end: Node) -> Vec<Vec2> {
-        let mut path: Vec<Vec2> = Vec::new();
+    fn fail_or_fallback(
+        &self,
+        state: &SearchState,
+        closest_idx: usize,
+        error: PathError,
+        options: &PathOptions,
+    ) -> Result<PathResult, PathError> {
+        if options.fallback_to_nearest {
+            Ok(self.build_result(state, closest_idx, false))
+        } else {
+            Err(error)
+        }
+    }
 
-        let mut current_node = &end;
-        loop {
-            if let Some(parent) = &current_node.parent_node {
-                path.push(current_node.pos);
-                current_node = &parent;
-            } else {
-                break;
-            }
+    fn build_result(&self, state: &SearchState, end_idx: usize, reached_goal: bool) -> PathResult {
+        PathResult {
+            path: self.retrace_path(&state.parents, end_idx),
+            end: idx_to_vec2(end_idx as i32),
+            cost: state.g_costs[&end_idx],
+            nodes_expanded: state.nodes_expanded,
+            reached_goal,
+        }
+    }
+
+    fn retrace_path(&self, parents: &HashMap<usize, usize>, end_idx: usize) -> VecDeque<Vec2> {
+        let mut path: VecDeque<Vec2> = VecDeque::new();
+
+        let mut current_idx = end_idx;
+        while let Some(parent_idx) = parents.get(&current_idx) {
+            path.push_front(idx_to_vec2(current_idx as i32));
+            current_idx = *parent_idx;
         }
         path
     }
 
     fn evaluate_traversable_node_neighbours(
         &self,
-        node: &Node,
-        start_pos: Vec2,
-        target_pos: Vec2,
-    ) -> Vec<Node> {
-        let mut neigbours: Vec<Node> = Vec::new();
-        let diff_vectors = vec![
-            Vec2::new(1.0, 0.0),
-            Vec2::new(1.0, 1.0),
-            Vec2::new(0.0, 1.0),
-            Vec2::new(-1.0, 0.0),
-            Vec2::new(-1.0, -1.0),
-            Vec2::new(0.0, -1.0),
-            Vec2::new(1.0, -1.0),
-            Vec2::new(-1.0, 1.0),
-        ];
-
-        for diff in diff_vectors.iter() {
-            let current_neigbour_pos = node.pos + *diff * TILE_SIZE as f32;
-            if !self.map.in_bounds(current_neigbour_pos) {
+        idx: usize,
+        options: &PathOptions,
+    ) -> Vec<(usize, f32)> {
+        let mut neighbours: Vec<(usize, f32)> = Vec::new();
+        let (x, y) = tile_coords(idx);
+
+        for (dx, dy) in NEIGHBOUR_OFFSETS {
+            let (nx, ny) = (x + dx, y + dy);
+            if nx < 0 || ny < 0 || nx >= MAP_WIDTH as i32 || ny >= MAP_HEIGHT as i32 {
                 continue;
             }
-            let neighbour_idx = vec2_to_idx(current_neigbour_pos);
-            if self.map.tiles[neighbour_idx].is_traversable() {
-                let destination_vec: Vec2 = target_pos - current_neigbour_pos;
-                let start_vec: Vec2 = start_pos - current_neigbour_pos;
-                neigbours.push(Node {
-                    g_cost: start_vec.length().ceil(),
-                    h_cost: destination_vec.length().ceil(),
-                    parent_node: Some(Box::new(node.clone())),
-                    pos: current_neigbour_pos,
-                });
+            if dx != 0 && dy != 0 {
+                let first_side_open = self.can_enter_tile(nx, y, options.locomotion);
+                let second_side_open = self.can_enter_tile(x, ny, options.locomotion);
+                if !self
+                    .corner_cutting
+                    .allows(first_side_open, second_side_open)
+                {
+                    continue;
+                }
+            }
+            let neighbour_idx = ny as usize * MAP_WIDTH + nx as usize;
+            if let Some(tile_cost) = options.locomotion.tile_cost(&self.map.tiles[neighbour_idx]) {
+                let step_length = if dx != 0 && dy != 0 {
+                    std::f32::consts::SQRT_2
+                } else {
+                    1.0
+                };
+                neighbours.push((neighbour_idx, step_length * tile_cost));
             }
         }
 
-        neigbours
+        neighbours
     }
 }
 
-fn vec_contains_node(nodes: &Vec<Node>, node: &Node) -> bool {
-    for n in nodes.iter() {
-        if n.pos == node.pos {
-            return true;
+const NEIGHBOUR_OFFSETS: [(i32, i32); 8] = [
+    (1, 0),
+    (1, 1),
+    (0, 1),
+    (-1, 0),
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+    (-1, 1),
+];
+
+fn tile_coords(idx: usize) -> (i32, i32) {
+    ((idx % MAP_WIDTH) as i32, (idx / MAP_WIDTH) as i32)
+}
+
+// Distance in tiles when moving in 8 directions, diagonal steps cost sqrt(2)
+fn octile_distance(from_idx: usize, to_idx: usize) -> f32 {
+    let (from_x, from_y) = tile_coords(from_idx);
+    let (to_x, to_y) = tile_coords(to_idx);
+    let dx = (from_x - to_x).abs() as f32;
+    let dy = (from_y - to_y).abs() as f32;
+    (dx + dy) + (std::f32::consts::SQRT_2 - 2.0) * dx.min(dy)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Open land everywhere, without the trees Map::new starts with
+    fn open_map() -> Map {
+        let mut map = Map::new();
+        for tile in map.tiles.iter_mut() {
+            tile.tree_noise_value = 1.0;
+        }
+        map
+    }
+
+    fn block(map: &mut Map, x: usize, y: usize) {
+        map.tiles[y * MAP_WIDTH + x].tile_type = TileType::Water;
+    }
+
+    fn tile_center(x: usize, y: usize) -> Vec2 {
+        (Vec2::new(x as f32, y as f32) + 0.5) * TILE_SIZE as f32
+    }
+
+    #[test]
+    fn diagonal_step_is_taken_when_both_sides_are_open() {
+        let pathfinder = Pathfinder::new(open_map());
+        let result = pathfinder
+            .a_star(
+                tile_center(5, 5),
+                tile_center(6, 6),
+                &PathOptions::default(),
+            )
+            .unwrap();
+        assert_eq!(result.path.len(), 1);
+        assert!((result.cost - std::f32::consts::SQRT_2).abs() < 1e-5);
+    }
+
+    #[test]
+    fn diagonal_step_goes_around_a_blocked_corner() {
+        let mut map = open_map();
+        block(&mut map, 6, 5);
+        let pathfinder = Pathfinder::new(map);
+        let result = pathfinder
+            .a_star(
+                tile_center(5, 5),
+                tile_center(6, 6),
+                &PathOptions::default(),
+            )
+            .unwrap();
+        assert_eq!(
+            Vec::from(result.path),
+            vec![
+                idx_to_vec2(6 * MAP_WIDTH as i32 + 5),
+                idx_to_vec2(6 * MAP_WIDTH as i32 + 6)
+            ]
+        );
+        assert!((result.cost - 2.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn diagonal_step_cant_squeeze_between_two_blocked_tiles() {
+        let mut map = open_map();
+        block(&mut map, 1, 0);
+        block(&mut map, 0, 1);
+        let pathfinder = Pathfinder::new(map);
+        let result = pathfinder.a_star(
+            tile_center(0, 0),
+            tile_center(1, 1),
+            &PathOptions::default(),
+        );
+        assert_eq!(result.err(), Some(PathError::Unreachable));
+    }
+
+    #[test]
+    fn segment_through_a_blocked_corner_is_not_clear() {
+        let mut map = open_map();
+        block(&mut map, 6, 5);
+        let pathfinder = Pathfinder::new(map);
+        let locomotion = Locomotion::Walker;
+        assert!(!pathfinder.is_segment_clear(tile_center(5, 5), tile_center(6, 6), locomotion));
+        assert!(pathfinder.is_segment_clear(tile_center(5, 5), tile_center(5, 8), locomotion));
+    }
+
+    #[test]
+    fn nearest_tile_picks_the_cheapest_goal_not_the_closest() {
+        // A wall of water between the start and the goal right above it
+        let mut map = open_map();
+        for x in 0..=15 {
+            block(&mut map, x, 7);
         }
+        let pathfinder = Pathfinder::new(map);
+        let behind_wall = tile_center(5, 8);
+        let open_field = tile_center(5, 1);
+        let result = pathfinder
+            .nearest_tile(
+                tile_center(5, 5),
+                &[behind_wall, open_field],
+                100.0,
+                &PathOptions::default(),
+            )
+            .unwrap();
+        assert_eq!(vec2_to_idx(result.end), vec2_to_idx(open_field));
+        assert!((result.cost - 4.0).abs() < 1e-5);
+        assert!(result.reached_goal);
+    }
+
+    #[test]
+    fn nearest_tile_is_unreachable_when_no_goal_can_be_reached() {
+        let mut map = open_map();
+        for (x, y) in [
+            (19, 19),
+            (20, 19),
+            (21, 19),
+            (19, 20),
+            (21, 20),
+            (19, 21),
+            (20, 21),
+            (21, 21),
+        ] {
+            block(&mut map, x, y);
+        }
+        let pathfinder = Pathfinder::new(map);
+        let options = PathOptions::default();
+        assert_eq!(
+            pathfinder
+                .nearest_tile(tile_center(5, 5), &[tile_center(20, 20)], 1000.0, &options)
+                .err(),
+            Some(PathError::Unreachable)
+        );
+        // Further than max_cost counts as out of reach too
+        assert_eq!(
+            pathfinder
+                .nearest_tile(tile_center(5, 5), &[tile_center(5, 30)], 10.0, &options)
+                .err(),
+            Some(PathError::Unreachable)
+        );
+    }
+
+    #[test]
+    fn nearest_where_stops_at_max_nodes() {
+        let pathfinder = Pathfinder::new(open_map());
+        let goal_idx = vec2_to_idx(tile_center(5, 40));
+        let options = PathOptions {
+            max_nodes: Some(10),
+            ..default()
+        };
+        assert_eq!(
+            pathfinder
+                .nearest_where(tile_center(5, 5), 1000.0, &options, |idx| idx == goal_idx)
+                .err(),
+            Some(PathError::BudgetExceeded)
+        );
+
+        let result = pathfinder
+            .nearest_where(tile_center(5, 5), 1000.0, &options, |idx| {
+                idx == vec2_to_idx(tile_center(6, 5))
+            })
+            .unwrap();
+        assert!(result.nodes_expanded <= 10);
+    }
+
+    #[test]
+    fn one_side_open_cuts_a_corner_but_not_between_two_blocked_tiles() {
+        let mut map = open_map();
+        block(&mut map, 6, 5);
+        block(&mut map, 1, 0);
+        block(&mut map, 0, 1);
+        let pathfinder = Pathfinder::new(map).with_corner_cutting(CornerCutting::OneSideOpen);
+        let options = PathOptions::default();
+
+        let result = pathfinder
+            .a_star(tile_center(5, 5), tile_center(6, 6), &options)
+            .unwrap();
+        assert_eq!(result.path.len(), 1);
+        assert!((result.cost - std::f32::consts::SQRT_2).abs() < 1e-5);
+        assert_eq!(
+            pathfinder
+                .a_star(tile_center(0, 0), tile_center(1, 1), &options)
+                .err(),
+            Some(PathError::Unreachable)
+        );
+
+        let locomotion = Locomotion::Walker;
+        assert!(pathfinder.is_segment_clear(tile_center(5, 5), tile_center(6, 6), locomotion));
+        assert!(!pathfinder.is_segment_clear(tile_center(0, 0), tile_center(1, 1), locomotion));
+    }
+
+    #[test]
+    fn always_squeezes_between_two_blocked_tiles() {
+        let mut map = open_map();
+        block(&mut map, 1, 0);
+        block(&mut map, 0, 1);
+        let pathfinder = Pathfinder::new(map).with_corner_cutting(CornerCutting::Always);
+        let result = pathfinder
+            .a_star(
+                tile_center(0, 0),
+                tile_center(1, 1),
+                &PathOptions::default(),
+            )
+            .unwrap();
+        assert_eq!(result.path.len(), 1);
+        assert!((result.cost - std::f32::consts::SQRT_2).abs() < 1e-5);
+        assert!(pathfinder.is_segment_clear(
+            tile_center(0, 0),
+            tile_center(1, 1),
+            Locomotion::Walker
+        ));
     }
-    return false;
 }
@@ -0,0 +1,188 @@
+use bevy::utils::HashSet;
+
+use crate::prelude::*;
+
+// Anything this close gets noticed no matter where the animal is looking
+const NEAR_SENSE_RADIUS: f32 = (TILE_SIZE * 2) as f32;
+// Share of the sight radius left to a sleeping animal
+const ASLEEP_SENSE_FACTOR: f32 = 0.25;
+
+pub struct PerceptionPlugin;
+
+impl Plugin for PerceptionPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(Self::perceive);
+    }
+}
+
+#[derive(Component, Clone, Copy)]
+pub struct Senses {
+    // In world units
+    pub sight_radius: f32,
+    // Full angle in radians, centered on the direction the animal is facing
+    pub field_of_view: f32,
+}
+
+#[derive(Clone, Copy)]
+pub struct PerceivedAnimal {
+    pub entity: Entity,
+    pub pos: Vec2,
+    pub animal_type: AnimalType,
+}
+
+// Everything the animal could see this tick, AI systems read this instead of querying the world
+#[derive(Component, Default)]
+pub struct Perceived {
+    pub plants: Vec<(Entity, Vec2)>,
+    pub carcasses: Vec<(Entity, Vec2)>,
+    // Only the water tiles at the edge of a lake, those are the ones that matter for drinking
+    pub water: Vec<Vec2>,
+    pub animals: Vec<PerceivedAnimal>,
+}
+
+impl Perceived {
+    pub fn clear(&mut self) {
+        self.plants.clear();
+        self.carcasses.clear();
+        self.water.clear();
+        self.animals.clear();
+    }
+}
+
+impl PerceptionPlugin {
+    #[allow(clippy::type_complexity)]
+    fn perceive(
+        mut observer_query: Query<(
+            Entity,
+            &Pos,
+            &Senses,
+            &AnimalDirection,
+            &AnimalState,
+            &mut Perceived,
+            Option<&InBurrow>,
+        )>,
+        // Animals inside a burrow can't be seen
+        animal_query: Query<(Entity, &Pos, &AnimalType), (With<Animal>, Without<InBurrow>)>,
+        plant_query: Query<(Entity, &Pos, &Plant)>,
+        carcass_query: Query<(Entity, &Pos), With<Carcass>>,
+        map: Res<Map>,
+        animal_direction_map: Res<AnimalDirectionVectorMap>,
+    ) {
+        let sight_blockers: HashSet<usize> = plant_query
+            .iter()
+            .filter(|(_, pos, plant)| plant.blocks_sight() && map.in_bounds(pos.0))
+            .map(|(_, pos, _)| vec2_to_idx(pos.0))
+            .collect();
+
+        for (observer, pos, senses, direction, state, mut perceived, in_burrow) in
+            observer_query.iter_mut()
+        {
+            perceived.clear();
+            if in_burrow.is_some() {
+                continue;
+            }
+            // Asleep, only something close enough to be heard wakes the animal, from any side
+            let asleep = *state == AnimalState::Sleeping;
+            let sight_radius = if asleep {
+                senses.sight_radius * ASLEEP_SENSE_FACTOR
+            } else {
+                senses.sight_radius
+            };
+            let facing = *animal_direction_map.0.get(direction).unwrap();
+            let can_see = |target: Vec2| {
+                let to_target = target - pos.0;
+                to_target.length() <= sight_radius
+                    && (asleep || is_in_field_of_view(facing, to_target, senses.field_of_view))
+                    && has_line_of_sight(&map, &sight_blockers, pos.0, target)
+            };
+
+            for (entity, plant_pos, _) in plant_query.iter() {
+                if can_see(plant_pos.0) {
+                    perceived.plants.push((entity, plant_pos.0));
+                }
+            }
+
+            for (entity, carcass_pos) in carcass_query.iter() {
+                if can_see(carcass_pos.0) {
+                    perceived.carcasses.push((entity, carcass_pos.0));
+                }
+            }
+
+            for (entity, animal_pos, animal_type) in animal_query.iter() {
+                if entity != observer && can_see(animal_pos.0) {
+                    perceived.animals.push(PerceivedAnimal {
+                        entity,
+                        pos: animal_pos.0,
+                        animal_type: *animal_type,
+                    });
+                }
+            }
+
+            let sight_radius_in_tiles = (sight_radius / TILE_SIZE as f32).ceil() as i32;
+            let center_x = (pos.0.x / TILE_SIZE as f32).floor() as i32;
+            let center_y = (pos.0.y / TILE_SIZE as f32).floor() as i32;
+            for y in (center_y - sight_radius_in_tiles)..=(center_y + sight_radius_in_tiles) {
+                for x in (center_x - sight_radius_in_tiles)..=(center_x + sight_radius_in_tiles) {
+                    if x < 0 || y < 0 || x >= MAP_WIDTH as i32 || y >= MAP_HEIGHT as i32 {
+                        continue;
+                    }
+                    let idx = y as usize * MAP_WIDTH + x as usize;
+                    if map.tiles[idx].tile_type != TileType::Water || !map.is_next_to_land(idx) {
+                        continue;
+                    }
+                    let water_pos = idx_to_vec2(idx as i32);
+                    if can_see(water_pos) {
+                        perceived.water.push(water_pos);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn is_in_field_of_view(facing: Vec2, to_target: Vec2, field_of_view: f32) -> bool {
+    if to_target.length() <= NEAR_SENSE_RADIUS {
+        return true;
+    }
+    facing.angle_between(to_target).abs() <= field_of_view / 2.0
+}
+
+// Bresenham over tiles, trees and tall plants block the view but the target tile itself never does
+pub fn has_line_of_sight(map: &Map, sight_blockers: &HashSet<usize>, from: Vec2, to: Vec2) -> bool {
+    let (mut x, mut y) = (
+        (from.x / TILE_SIZE as f32).floor() as i32,
+        (from.y / TILE_SIZE as f32).floor() as i32,
+    );
+    let (end_x, end_y) = (
+        (to.x / TILE_SIZE as f32).floor() as i32,
+        (to.y / TILE_SIZE as f32).floor() as i32,
+    );
+    let dx = (end_x - x).abs();
+    let dy = -(end_y - y).abs();
+    let step_x = if x < end_x { 1 } else { -1 };
+    let step_y = if y < end_y { 1 } else { -1 };
+    let mut error = dx + dy;
+
+    while (x, y) != (end_x, end_y) {
+        let doubled_error = 2 * error;
+        if doubled_error >= dy {
+            error += dy;
+            x += step_x;
+        }
+        if doubled_error <= dx {
+            error += dx;
+            y += step_y;
+        }
+        if (x, y) == (end_x, end_y) {
+            break;
+        }
+        if x < 0 || y < 0 || x >= MAP_WIDTH as i32 || y >= MAP_HEIGHT as i32 {
+            return false;
+        }
+        let idx = y as usize * MAP_WIDTH + x as usize;
+        if map.tiles[idx].has_tree() || sight_blockers.contains(&idx) {
+            return false;
+        }
+    }
+    true
+}
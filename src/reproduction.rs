@@ -0,0 +1,326 @@
+use bevy::utils::HashSet;
+use serde::Deserialize;
+
+use crate::prelude::*;
+
+// Per second, only while the animal is mature and well fed
+const URGE_RATE: f32 = 0.03;
+// Both animals need at least this much urge to pair up
+const MATING_URGE_THRESHOLD: f32 = 0.5;
+const WELL_FED_LIMIT: f32 = NEED_LIMIT * 0.5;
+const MATING_DISTANCE: f32 = TILE_SIZE as f32 * 1.5;
+// The pair splits up if they don't manage to get together in time
+const PAIRING_TIMEOUT: f64 = 30.0;
+// Re-plan the approach when the partner has moved this far away from the current path's end
+const PARTNER_MOVED_DISTANCE: f32 = (TILE_SIZE * 3) as f32;
+
+pub struct ReproductionPlugin;
+
+impl Plugin for ReproductionPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(Self::increase_urge)
+            .add_system(Self::pair_up)
+            .add_system(Self::break_up_pairs)
+            .add_system(Self::approach_mate)
+            .add_system(Self::mate)
+            .add_system(Self::gestate)
+            .add_system(Self::tick_mating_cooldowns);
+    }
+}
+
+#[derive(Component, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Sex {
+    Male,
+    Female,
+}
+
+impl Sex {
+    pub fn random() -> Self {
+        if thread_rng().gen_bool(0.5) {
+            Sex::Male
+        } else {
+            Sex::Female
+        }
+    }
+}
+
+// 0 to 1, how much the animal wants to mate
+#[derive(Component, Default)]
+pub struct ReproductiveUrge(pub f32);
+
+#[derive(Component, Clone, Copy, Deserialize)]
+pub struct ReproductionParams {
+    pub min_litter_size: u32,
+    pub max_litter_size: u32,
+    // Seconds
+    pub gestation_period: f32,
+    pub mating_cooldown: f32,
+}
+
+#[derive(Component)]
+pub struct Mate {
+    pub partner: Entity,
+    paired_at: f64,
+}
+
+#[derive(Component)]
+pub struct Pregnancy {
+    pub timer: Timer,
+    pub litter_size: u32,
+    // Kept here rather than looked up at birth, the father might not live to see it
+    pub father_genome: Genome,
+}
+
+#[derive(Component)]
+pub struct MatingCooldown(pub Timer);
+
+impl ReproductionPlugin {
+    #[allow(clippy::type_complexity)]
+    fn increase_urge(
+        mut query: Query<
+            (&mut ReproductiveUrge, &LifeStage, &Hunger, &Thirst),
+            (Without<Pregnancy>, Without<MatingCooldown>),
+        >,
+        time: Res<Time>,
+    ) {
+        for (mut urge, life_stage, hunger, thirst) in query.iter_mut() {
+            let fertility = life_stage.fertility();
+            if fertility <= 0.0 || hunger.0 > WELL_FED_LIMIT || thirst.0 > WELL_FED_LIMIT {
+                continue;
+            }
+            urge.0 = (urge.0 + URGE_RATE * fertility * time.delta_seconds()).min(1.0);
+        }
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn pair_up(
+        query: Query<
+            (Entity, &AnimalType, &Sex, &ReproductiveUrge, &Perceived),
+            (Without<Mate>, Without<Pregnancy>, Without<MatingCooldown>),
+        >,
+        time: Res<Time>,
+        mut commands: Commands,
+    ) {
+        let mut paired: HashSet<Entity> = HashSet::new();
+        for (entity, animal_type, sex, urge, perceived) in query.iter() {
+            if urge.0 < MATING_URGE_THRESHOLD || paired.contains(&entity) {
+                continue;
+            }
+
+            let partner = perceived.animals.iter().find(|other| {
+                other.animal_type == *animal_type
+                    && !paired.contains(&other.entity)
+                    && query
+                        .get(other.entity)
+                        .is_ok_and(|(_, _, other_sex, other_urge, _)| {
+                            *other_sex != *sex && other_urge.0 >= MATING_URGE_THRESHOLD
+                        })
+            });
+            if let Some(partner) = partner {
+                let paired_at = time.seconds_since_startup();
+                paired.insert(entity);
+                paired.insert(partner.entity);
+                commands.entity(entity).insert(Mate {
+                    partner: partner.entity,
+                    paired_at,
+                });
+                commands.entity(partner.entity).insert(Mate {
+                    partner: entity,
+                    paired_at,
+                });
+            }
+        }
+    }
+
+    fn break_up_pairs(query: Query<(Entity, &Mate)>, time: Res<Time>, mut commands: Commands) {
+        for (entity, mate) in query.iter() {
+            let partner_left = !query
+                .get(mate.partner)
+                .is_ok_and(|(_, partner_mate)| partner_mate.partner == entity);
+            let timed_out = time.seconds_since_startup() - mate.paired_at > PAIRING_TIMEOUT;
+            if partner_left || timed_out {
+                commands.entity(entity).remove::<Mate>();
+            }
+        }
+    }
+
+    // The male goes to the female, she waits for him
+    #[allow(clippy::type_complexity)]
+    fn approach_mate(
+        query: Query<(
+            Entity,
+            &Pos,
+            &Sex,
+            &Mate,
+            &Decision,
+            &Memory,
+            Option<&Path>,
+            Option<&PathfindingTask>,
+        )>,
+        mut commands: Commands,
+    ) {
+        for (entity, pos, sex, mate, decision, memory, path, pending_path) in query.iter() {
+            if *sex != Sex::Male || decision.action != Action::Mate || pending_path.is_some() {
+                continue;
+            }
+            // He only knows where he last saw her
+            let partner_pos = match memory.recall_entity(MemoryKind::Mate, mate.partner) {
+                Some(remembered) => remembered.pos,
+                None => continue,
+            };
+            if (partner_pos - pos.0).length() <= MATING_DISTANCE {
+                continue;
+            }
+
+            let needs_new_path = match path {
+                Some(path) => path
+                    .0
+                    .back()
+                    .is_none_or(|end| (*end - partner_pos).length() > PARTNER_MOVED_DISTANCE),
+                None => true,
+            };
+            if needs_new_path {
+                let mut request = PathRequest::new(partner_pos);
+                request.options.fallback_to_nearest = true;
+                commands.entity(entity).insert(request);
+            }
+        }
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn mate(
+        query: Query<(
+            Entity,
+            &Pos,
+            &Sex,
+            &Mate,
+            &Decision,
+            &ReproductionParams,
+            &Genome,
+            Option<&SelectedAnimal>,
+        )>,
+        mut commands: Commands,
+    ) {
+        for (entity, pos, sex, mate, decision, params, genome, selected) in query.iter() {
+            if *sex != Sex::Female || decision.action != Action::Mate {
+                continue;
+            }
+            let (
+                partner,
+                partner_pos,
+                _,
+                _,
+                partner_decision,
+                partner_params,
+                partner_genome,
+                partner_selected,
+            ) = match query.get(mate.partner) {
+                Ok(partner) => partner,
+                Err(_) => continue,
+            };
+            if partner_decision.action != Action::Mate
+                || (partner_pos.0 - pos.0).length() > MATING_DISTANCE
+            {
+                continue;
+            }
+
+            let litter_size = (genome.litter_size + thread_rng().gen_range(-0.5..0.5))
+                .round()
+                .clamp(params.min_litter_size as f32, params.max_litter_size as f32)
+                as u32;
+            if selected.is_some() || partner_selected.is_some() {
+                println!(
+                    "{:?} and {:?} mated, expecting {} young",
+                    entity, partner, litter_size
+                );
+            }
+            commands
+                .entity(entity)
+                .remove::<Mate>()
+                .insert(ReproductiveUrge(0.0))
+                .insert(Pregnancy {
+                    timer: Timer::from_seconds(params.gestation_period, false),
+                    litter_size,
+                    father_genome: partner_genome.clone(),
+                });
+            commands
+                .entity(partner)
+                .remove::<Mate>()
+                .remove::<Path>()
+                .remove::<Velocity>()
+                .insert(ReproductiveUrge(0.0))
+                .insert(MatingCooldown(Timer::from_seconds(
+                    partner_params.mating_cooldown,
+                    false,
+                )));
+        }
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn gestate(
+        mut query: Query<(
+            Entity,
+            &Pos,
+            &AnimalType,
+            &ReproductionParams,
+            &Genome,
+            &mut Pregnancy,
+            Option<&Home>,
+            Option<&SelectedAnimal>,
+        )>,
+        burrow_query: Query<&Pos, With<Burrow>>,
+        mutation_config: Res<MutationConfig>,
+        species: Res<SpeciesRegistry>,
+        time: Res<Time>,
+        mut commands: Commands,
+    ) {
+        for (entity, pos, animal_type, params, genome, mut pregnancy, home, selected) in
+            query.iter_mut()
+        {
+            pregnancy.timer.tick(time.delta());
+            if !pregnancy.timer.finished() {
+                continue;
+            }
+
+            // The young are born in the burrow and grow up calling it home too
+            let burrow = home.and_then(|home| Some((home.0, burrow_query.get(home.0).ok()?.0)));
+            let birth_pos = burrow.map_or(pos.0, |(_, burrow_pos)| burrow_pos);
+            for _ in 0..pregnancy.litter_size {
+                let offspring_genome =
+                    Genome::crossover(genome, &pregnancy.father_genome, &mutation_config);
+                let mut offspring = commands.spawn_bundle(AnimalBundle::with_genome(
+                    &species,
+                    *animal_type,
+                    birth_pos,
+                    offspring_genome,
+                ));
+                if let Some((burrow, _)) = burrow {
+                    offspring.insert(Home(burrow));
+                }
+            }
+            if selected.is_some() {
+                println!("{:?} gave birth to {} young", entity, pregnancy.litter_size);
+            }
+            commands
+                .entity(entity)
+                .remove::<Pregnancy>()
+                .insert(MatingCooldown(Timer::from_seconds(
+                    params.mating_cooldown,
+                    false,
+                )));
+        }
+    }
+
+    fn tick_mating_cooldowns(
+        mut query: Query<(Entity, &mut MatingCooldown)>,
+        time: Res<Time>,
+        mut commands: Commands,
+    ) {
+        for (entity, mut cooldown) in query.iter_mut() {
+            cooldown.0.tick(time.delta());
+            if cooldown.0.finished() {
+                commands.entity(entity).remove::<MatingCooldown>();
+            }
+        }
+    }
+}
@@ -0,0 +1,145 @@
+use bevy::utils::HashMap;
+
+use crate::prelude::*;
+
+// In tiles, how far away an animal without a burrow looks for cover to sleep in
+const SLEEP_SPOT_SEARCH_RADIUS: i32 = 8;
+// Cover a spot has to offer over the current one to be worth walking to
+const MIN_COVER_GAIN: f32 = 0.1;
+// Close enough to the chosen spot to lie down
+const ARRIVE_DISTANCE: f32 = (TILE_SIZE * 2) as f32;
+const SLEEP_SPOT_SEARCH_BUDGET: usize = 800;
+
+pub struct SleepPlugin;
+
+impl Plugin for SleepPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(Self::find_sleeping_spot)
+            .add_system(Self::fall_asleep)
+            .add_system(Self::wake_up);
+    }
+}
+
+// Has picked a place to sleep and is on the way there, or already lying in it
+#[derive(Component)]
+pub struct SleepingSpot;
+
+impl SleepPlugin {
+    // Animals with a burrow sleep in it, see BurrowPlugin. Herd members bed down together in the
+    // middle of the herd, loners look for a spot surrounded by trees.
+    #[allow(clippy::type_complexity)]
+    fn find_sleeping_spot(
+        query: Query<
+            (Entity, &Pos, &Locomotion, &Decision, Option<&Herd>),
+            (
+                Without<SleepingSpot>,
+                Without<Home>,
+                Without<InBurrow>,
+                Without<Path>,
+                Without<PathRequest>,
+                Without<PathfindingTask>,
+            ),
+        >,
+        member_query: Query<(&Herd, &Pos)>,
+        map: Res<Map>,
+        mut commands: Commands,
+    ) {
+        let mut herd_positions: HashMap<Entity, (Vec2, usize)> = HashMap::new();
+        for (herd, pos) in member_query.iter() {
+            let (sum, count) = herd_positions.entry(herd.leader).or_insert((Vec2::ZERO, 0));
+            *sum += pos.0;
+            *count += 1;
+        }
+
+        for (entity, pos, locomotion, decision, herd) in query.iter() {
+            if decision.action != Action::Sleep {
+                continue;
+            }
+            let herd_center = herd
+                .and_then(|herd| herd_positions.get(&herd.leader))
+                .filter(|(_, count)| *count > 1)
+                .map(|(sum, count)| *sum / *count as f32);
+            let spot = herd_center
+                .or_else(|| find_cover(pos.0, *locomotion, &map))
+                .unwrap_or(pos.0);
+
+            commands.entity(entity).insert(SleepingSpot);
+            if (spot - pos.0).length() > ARRIVE_DISTANCE {
+                commands
+                    .entity(entity)
+                    .insert(Gait::Walk)
+                    .insert(PathRequest {
+                        goal: spot,
+                        options: PathOptions {
+                            max_nodes: Some(SLEEP_SPOT_SEARCH_BUDGET),
+                            fallback_to_nearest: true,
+                            locomotion: *locomotion,
+                        },
+                    });
+            }
+        }
+    }
+
+    // Lies down once the animal is in its burrow or has got as close to its spot as it can
+    #[allow(clippy::type_complexity)]
+    fn fall_asleep(
+        mut query: Query<
+            (Entity, &Decision, &mut AnimalState),
+            (
+                Or<(With<SleepingSpot>, With<InBurrow>)>,
+                Without<Path>,
+                Without<PathRequest>,
+                Without<PathfindingTask>,
+            ),
+        >,
+        mut commands: Commands,
+    ) {
+        for (entity, decision, mut state) in query.iter_mut() {
+            if decision.action != Action::Sleep || *state == AnimalState::Sleeping {
+                continue;
+            }
+            commands.entity(entity).remove::<Velocity>();
+            *state = AnimalState::Sleeping;
+        }
+    }
+
+    fn wake_up(
+        mut query: Query<(Entity, &Decision, &mut AnimalState, Option<&SleepingSpot>)>,
+        mut commands: Commands,
+    ) {
+        for (entity, decision, mut state, spot) in query.iter_mut() {
+            if decision.action == Action::Sleep {
+                continue;
+            }
+            if *state == AnimalState::Sleeping {
+                *state = AnimalState::Idle;
+            }
+            if spot.is_some() {
+                commands.entity(entity).remove::<SleepingSpot>();
+            }
+        }
+    }
+}
+
+// The spot with the most trees around it, None when the animal is already about as covered as
+// it's going to get
+fn find_cover(pos: Vec2, locomotion: Locomotion, map: &Map) -> Option<Vec2> {
+    let mut best: Option<(Vec2, f32)> = None;
+    for dy in -SLEEP_SPOT_SEARCH_RADIUS..=SLEEP_SPOT_SEARCH_RADIUS {
+        for dx in -SLEEP_SPOT_SEARCH_RADIUS..=SLEEP_SPOT_SEARCH_RADIUS {
+            let spot = pos + Vec2::new(dx as f32, dy as f32) * TILE_SIZE as f32;
+            if !map
+                .tile_at(spot)
+                .is_some_and(|tile| locomotion.can_enter(tile))
+            {
+                continue;
+            }
+            let cover = cover_around(spot, map);
+            if best.is_none_or(|(_, best_cover)| cover > best_cover) {
+                best = Some((spot, cover));
+            }
+        }
+    }
+    best.filter(|(_, cover)| *cover >= cover_around(pos, map) + MIN_COVER_GAIN)
+        .map(|(spot, _)| spot)
+}
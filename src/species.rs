@@ -0,0 +1,397 @@
+use std::collections::HashMap as StdHashMap;
+use std::fs;
+use std::path::PathBuf;
+
+use serde::Deserialize;
+
+use crate::prelude::*;
+
+// Which tiles a creature can enter and how expensive it is to move through them
+#[derive(Component, Clone, Copy, PartialEq, Eq, Hash, Debug, Default, Deserialize)]
+pub enum Locomotion {
+    #[default]
+    Walker,
+    Swimmer,
+    Wader,
+    Flyer,
+    Climber,
+}
+
+impl Locomotion {
+    // None means the tile can't be entered at all
+    pub fn tile_cost(&self, tile: &Tile) -> Option<f32> {
+        match self {
+            Locomotion::Walker => tile.is_traversable().then_some(1.0),
+            Locomotion::Swimmer => (tile.tile_type == TileType::Water).then_some(1.0),
+            Locomotion::Wader => match tile.tile_type {
+                TileType::Water => Some(2.5),
+                TileType::Land => (!tile.has_tree()).then_some(1.0),
+            },
+            Locomotion::Flyer => Some(1.0),
+            Locomotion::Climber => match tile.tile_type {
+                TileType::Water => None,
+                TileType::Land if tile.has_tree() => Some(2.0),
+                TileType::Land => Some(1.0),
+            },
+        }
+    }
+
+    pub fn can_enter(&self, tile: &Tile) -> bool {
+        self.tile_cost(tile).is_some()
+    }
+}
+
+#[derive(Component, Clone, Copy, PartialEq, Eq, Debug, Deserialize)]
+pub enum Diet {
+    // Eats plants
+    Herbivore,
+    // Hunts other animals and eats their carcasses
+    Carnivore,
+}
+
+#[derive(Bundle)]
+pub struct AnimalBundle {
+    pub animal: Animal,
+    pub pos: Pos,
+    pub animal_type: AnimalType,
+    pub locomotion: Locomotion,
+    pub diet: Diet,
+    pub state: AnimalState,
+    pub direction: AnimalDirection,
+    pub hunger: Hunger,
+    pub energy: Energy,
+    pub thirst: Thirst,
+    pub fatigue: Fatigue,
+    pub infection: Infection,
+    pub senses: Senses,
+    pub perceived: Perceived,
+    pub decision: Decision,
+    pub home_range: HomeRange,
+    pub exploration: Exploration,
+    pub memory: Memory,
+    pub age: Age,
+    pub life_cycle: LifeCycle,
+    pub life_stage: LifeStage,
+    pub sex: Sex,
+    pub reproductive_urge: ReproductiveUrge,
+    pub reproduction_params: ReproductionParams,
+    pub movement_stats: MovementStats,
+    pub gait: Gait,
+    pub momentum: Momentum,
+    pub genome: Genome,
+}
+
+impl AnimalBundle {
+    pub fn new(species: &SpeciesRegistry, animal_type: AnimalType, pos: Vec2) -> Self {
+        let genome = Genome::for_species(species.get(animal_type));
+        Self::with_genome(species, animal_type, pos, genome)
+    }
+
+    pub fn with_genome(
+        species: &SpeciesRegistry,
+        animal_type: AnimalType,
+        pos: Vec2,
+        genome: Genome,
+    ) -> Self {
+        let definition = species.get(animal_type);
+        AnimalBundle {
+            animal: Animal,
+            pos: Pos(pos),
+            animal_type,
+            locomotion: definition.locomotion,
+            diet: definition.diet,
+            state: AnimalState::Idle,
+            direction: AnimalDirection::Down,
+            hunger: Hunger::default(),
+            energy: Energy::full(energy_capacity(body_mass(
+                definition,
+                &genome,
+                LifeStage::Newborn,
+            ))),
+            thirst: Thirst::default(),
+            fatigue: Fatigue::default(),
+            infection: Infection::default(),
+            senses: Senses {
+                sight_radius: genome.sense_radius,
+                field_of_view: definition.field_of_view(),
+            },
+            perceived: Perceived::default(),
+            decision: Decision::default(),
+            home_range: HomeRange {
+                center: pos,
+                radius: definition.home_range_radius(),
+            },
+            exploration: Exploration::default(),
+            memory: Memory::new(genome.memory_capacity.round() as usize),
+            age: Age::default(),
+            life_cycle: definition.life_cycle,
+            life_stage: LifeStage::Newborn,
+            sex: Sex::random(),
+            reproductive_urge: ReproductiveUrge::default(),
+            reproduction_params: definition.reproduction,
+            movement_stats: definition.movement_stats(&genome),
+            gait: Gait::default(),
+            momentum: Momentum::default(),
+            genome,
+        }
+    }
+
+    // Animals placed into the world at the start are already grown up
+    pub fn adult(mut self, species: &SpeciesRegistry) -> Self {
+        self.age = Age(self.life_cycle.adult_age);
+        self.life_stage = LifeStage::Adult;
+        self.energy = Energy::full(energy_capacity(body_mass(
+            species.get(self.animal_type),
+            &self.genome,
+            LifeStage::Adult,
+        )));
+        self
+    }
+}
+
+// Everything that makes a species what it is, read from a file in assets/species
+#[derive(Deserialize)]
+pub struct SpeciesDefinition {
+    pub name: String,
+    pub locomotion: Locomotion,
+    pub diet: Diet,
+    // Names of the species this one preys on
+    hunts: Vec<String>,
+    // Relative to a bunny
+    pub body_mass: f32,
+    // In tiles
+    home_range_radius: f32,
+    // Degrees
+    field_of_view: f32,
+    // None for species that live on their own
+    #[serde(default)]
+    pub max_herd_size: Option<usize>,
+    #[serde(default)]
+    pub digs_burrows: bool,
+    pub activity_pattern: ActivityPattern,
+    // How many adults are placed into the world at the start
+    pub initial_count: usize,
+    movement: MovementDefinition,
+    // Only for carnivores
+    #[serde(default)]
+    hunting: Option<HuntingDefinition>,
+    pub flee: FleeDefinition,
+    pub life_cycle: LifeCycle,
+    pub reproduction: ReproductionParams,
+    // Starting point of the founding population's genes
+    pub base_genome: Genome,
+    pub sprite: SpriteDefinition,
+    // Resolved from the names in hunts once every species is loaded
+    #[serde(skip)]
+    prey: Vec<AnimalType>,
+}
+
+#[derive(Deserialize)]
+struct MovementDefinition {
+    // Running speed relative to the walking speed from the genome
+    run_speed_factor: f32,
+    acceleration: f32,
+    // Degrees per second
+    turn_rate: f32,
+}
+
+// How a carnivore closes in on its prey, in tiles
+#[derive(Deserialize)]
+pub struct HuntingDefinition {
+    // Closer than this the hunter stops sneaking and goes for it
+    pounce_distance: f32,
+    kill_distance: f32,
+}
+
+impl HuntingDefinition {
+    // World units
+    pub fn pounce_distance(&self) -> f32 {
+        self.pounce_distance * TILE_SIZE as f32
+    }
+
+    // World units
+    pub fn kill_distance(&self) -> f32 {
+        self.kill_distance * TILE_SIZE as f32
+    }
+}
+
+// How the animal gets away from predators
+#[derive(Deserialize)]
+pub struct FleeDefinition {
+    // Seconds without seeing a predator before the animal calms down
+    pub calm_down_time: f64,
+    // In tiles, how far away the escape routes lead
+    escape_distance: f32,
+    // What counts when picking an escape route: heading away from the threats, and ending up
+    // next to trees where a predator would lose sight of the animal
+    pub away_weight: f32,
+    pub cover_weight: f32,
+}
+
+impl FleeDefinition {
+    // World units
+    pub fn escape_distance(&self) -> f32 {
+        self.escape_distance * TILE_SIZE as f32
+    }
+}
+
+// Every species in assets/species, an AnimalType is an index into it
+pub struct SpeciesRegistry {
+    species: Vec<SpeciesDefinition>,
+}
+
+impl SpeciesRegistry {
+    // Reads every .ron file in the directory, in file name order so that the handles come out the
+    // same on every run
+    pub fn load(dir: &str) -> Result<Self, String> {
+        let dir = assets_root().join(dir);
+        let mut paths: Vec<PathBuf> = fs::read_dir(&dir)
+            .map_err(|error| format!("couldn't read {}: {}", dir.display(), error))?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|extension| extension == "ron"))
+            .collect();
+        paths.sort();
+
+        let mut species = Vec::new();
+        for path in paths.iter() {
+            let contents = fs::read_to_string(path)
+                .map_err(|error| format!("couldn't read {}: {}", path.display(), error))?;
+            let definition: SpeciesDefinition = ron::from_str(&contents)
+                .map_err(|error| format!("{} is not a valid species: {}", path.display(), error))?;
+            definition.validate()?;
+            species.push(definition);
+        }
+        if species.is_empty() {
+            return Err(format!("no species defined in {}", dir.display()));
+        }
+
+        let names: StdHashMap<String, AnimalType> = species
+            .iter()
+            .enumerate()
+            .map(|(idx, definition)| (definition.name.clone(), AnimalType(idx)))
+            .collect();
+        for definition in species.iter_mut() {
+            definition.prey = definition
+                .hunts
+                .iter()
+                .map(|name| {
+                    names.get(name).copied().ok_or_else(|| {
+                        format!("{} hunts {}, which isn't defined", definition.name, name)
+                    })
+                })
+                .collect::<Result<_, _>>()?;
+        }
+        Ok(SpeciesRegistry { species })
+    }
+
+    pub fn all(&self) -> impl Iterator<Item = AnimalType> {
+        (0..self.species.len()).map(AnimalType)
+    }
+
+    pub fn get(&self, animal_type: AnimalType) -> &SpeciesDefinition {
+        &self.species[animal_type.0]
+    }
+
+    pub fn name(&self, animal_type: AnimalType) -> &str {
+        &self.get(animal_type).name
+    }
+}
+
+impl SpeciesDefinition {
+    pub fn hunts(&self, other: AnimalType) -> bool {
+        self.prey.contains(&other)
+    }
+
+    // World units
+    pub fn home_range_radius(&self) -> f32 {
+        self.home_range_radius * TILE_SIZE as f32
+    }
+
+    // Radians
+    pub fn field_of_view(&self) -> f32 {
+        self.field_of_view.to_radians()
+    }
+
+    pub fn movement_stats(&self, genome: &Genome) -> MovementStats {
+        MovementStats {
+            walk_speed: genome.speed,
+            run_speed: genome.speed * self.movement.run_speed_factor,
+            acceleration: self.movement.acceleration,
+            turn_rate: self.movement.turn_rate.to_radians(),
+        }
+    }
+
+    // Only carnivores have one, which validate makes sure of
+    pub fn hunting(&self) -> &HuntingDefinition {
+        self.hunting
+            .as_ref()
+            .expect("carnivores have hunting parameters")
+    }
+
+    // Carnivores need to know how to hunt. Every state needs frames for every direction, otherwise
+    // the sprite has nothing to show.
+    fn validate(&self) -> Result<(), String> {
+        if self.diet == Diet::Carnivore && self.hunting.is_none() {
+            return Err(format!(
+                "{} is a carnivore without hunting parameters",
+                self.name
+            ));
+        }
+        for state in AnimalState::iter() {
+            for direction in AnimalDirection::iter() {
+                let frames = self
+                    .sprite
+                    .animations
+                    .get(&state)
+                    .and_then(|directions| directions.get(&direction))
+                    .ok_or_else(|| {
+                        format!("{} has no {:?} {:?} animation", self.name, state, direction)
+                    })?;
+                if frames.frames == 0 {
+                    return Err(format!(
+                        "{} has no frames in its {:?} {:?} animation",
+                        self.name, state, direction
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// Same lookup as the asset server, so the species files sit next to the sprites
+fn assets_root() -> PathBuf {
+    let base = std::env::var("CARGO_MANIFEST_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| {
+            std::env::current_exe()
+                .ok()
+                .and_then(|exe| exe.parent().map(|dir| dir.to_path_buf()))
+                .unwrap_or_default()
+        });
+    base.join("assets")
+}
+
+// A handle into the species registry
+#[derive(Component, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct AnimalType(usize);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bundled_species_load() {
+        let species = SpeciesRegistry::load("species").unwrap();
+        let fox = species
+            .all()
+            .find(|animal_type| species.name(*animal_type) == "Fox")
+            .unwrap();
+        let bunny = species
+            .all()
+            .find(|animal_type| species.name(*animal_type) == "Bunny")
+            .unwrap();
+        assert!(species.get(fox).hunts(bunny));
+        assert!(!species.get(bunny).hunts(fox));
+    }
+}
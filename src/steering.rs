@@ -0,0 +1,219 @@
+use bevy::utils::HashMap;
+
+use crate::prelude::*;
+
+// Animals further apart than this don't affect each other
+const NEIGHBOUR_RADIUS: f32 = (TILE_SIZE * 3) as f32;
+const SEPARATION_RADIUS: f32 = (TILE_SIZE * 2) as f32;
+// Two animals closer than this count as colliding
+const COLLISION_DISTANCE: f32 = TILE_SIZE as f32 * 1.5;
+// Seconds ahead that collisions are looked for
+const AVOIDANCE_HORIZON: f32 = 1.0;
+const SEPARATION_WEIGHT: f32 = 4.0;
+const AVOIDANCE_WEIGHT: f32 = 1.5;
+// How far ahead the steered direction is checked for tiles the animal can't enter
+const LOOKAHEAD_DISTANCE: f32 = TILE_SIZE as f32;
+// Directions closer to opposite than this count as meeting head-on
+const HEAD_ON_DOT: f32 = -0.5;
+
+pub struct SteeringPlugin;
+
+impl Plugin for SteeringPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(SpatialGrid::default())
+            .add_system(
+                Self::update_spatial_grid
+                    .label(MovementSystem::Steer)
+                    .after(MovementSystem::FollowPath),
+            )
+            .add_system(
+                Self::steer
+                    .label(MovementSystem::Steer)
+                    .after(Self::update_spatial_grid)
+                    .before(MovementSystem::Move),
+            );
+    }
+}
+
+// Animal positions bucketed into cells, so that finding neighbours doesn't mean checking every
+// animal against every other one
+#[derive(Default)]
+pub struct SpatialGrid {
+    cells: HashMap<(i32, i32), Vec<(Entity, Vec2)>>,
+}
+
+impl SpatialGrid {
+    fn cell(pos: Vec2) -> (i32, i32) {
+        (
+            (pos.x / NEIGHBOUR_RADIUS).floor() as i32,
+            (pos.y / NEIGHBOUR_RADIUS).floor() as i32,
+        )
+    }
+
+    pub fn nearby(&self, pos: Vec2, radius: f32) -> Vec<(Entity, Vec2)> {
+        let cell_radius = (radius / NEIGHBOUR_RADIUS).ceil() as i32;
+        let (center_x, center_y) = Self::cell(pos);
+        let mut nearby = Vec::new();
+        for y in (center_y - cell_radius)..=(center_y + cell_radius) {
+            for x in (center_x - cell_radius)..=(center_x + cell_radius) {
+                if let Some(cell) = self.cells.get(&(x, y)) {
+                    nearby.extend(
+                        cell.iter()
+                            .filter(|(_, other_pos)| (*other_pos - pos).length() <= radius),
+                    );
+                }
+            }
+        }
+        nearby
+    }
+}
+
+// The direction the animal actually heads in after making room for the others, move_animals
+// uses this instead of Velocity when it's there
+#[derive(Component)]
+pub struct Steering {
+    pub direction: Vec2,
+    // 0 when the animal waits for another one to get through a narrow spot
+    pub speed_factor: f32,
+}
+
+impl SteeringPlugin {
+    #[allow(clippy::type_complexity)]
+    fn update_spatial_grid(
+        query: Query<(Entity, &Pos), (With<Animal>, Without<InBurrow>)>,
+        mut grid: ResMut<SpatialGrid>,
+    ) {
+        grid.cells.clear();
+        for (entity, pos) in query.iter() {
+            grid.cells
+                .entry(SpatialGrid::cell(pos.0))
+                .or_default()
+                .push((entity, pos.0));
+        }
+    }
+
+    // Separation keeps animals from stacking up, reciprocal avoidance makes two animals that are
+    // about to run into each other both sidestep by half. When there's no room to sidestep the
+    // animal with the higher entity id waits, so two animals can't block each other for good.
+    #[allow(clippy::type_complexity)]
+    fn steer(
+        mut query: Query<(
+            Entity,
+            &Pos,
+            &Momentum,
+            &Locomotion,
+            Option<&Velocity>,
+            Option<&mut Steering>,
+        )>,
+        neighbour_query: Query<(&Momentum, Option<&Velocity>)>,
+        grid: Res<SpatialGrid>,
+        map: Res<Map>,
+        mut commands: Commands,
+    ) {
+        for (entity, pos, momentum, locomotion, velocity, steering) in query.iter_mut() {
+            let preferred = velocity.map_or(Vec2::ZERO, |velocity| velocity.0);
+            if !preferred.is_finite() || preferred == Vec2::ZERO {
+                if steering.is_some() {
+                    commands.entity(entity).remove::<Steering>();
+                }
+                continue;
+            }
+            let own_velocity = momentum.velocity();
+
+            let mut separation = Vec2::ZERO;
+            let mut avoidance = Vec2::ZERO;
+            let mut head_on_with_priority = false;
+            for (other, other_pos) in grid.nearby(pos.0, NEIGHBOUR_RADIUS) {
+                if other == entity {
+                    continue;
+                }
+                let (other_momentum, other_velocity) = match neighbour_query.get(other) {
+                    Ok(neighbour) => neighbour,
+                    Err(_) => continue,
+                };
+
+                let offset = pos.0 - other_pos;
+                let distance = offset.length();
+                if distance < SEPARATION_RADIUS {
+                    // Animals on the exact same spot get pushed apart sideways
+                    let push = if distance > f32::EPSILON {
+                        offset / distance
+                    } else {
+                        -preferred.perp()
+                    };
+                    separation += push / distance.max(1.0);
+                }
+
+                let relative_pos = other_pos - pos.0;
+                let relative_velocity = own_velocity - other_momentum.velocity();
+                let relative_speed_squared = relative_velocity.length_squared();
+                if relative_speed_squared < f32::EPSILON {
+                    continue;
+                }
+                let time_to_closest = relative_pos.dot(relative_velocity) / relative_speed_squared;
+                if time_to_closest <= 0.0 || time_to_closest > AVOIDANCE_HORIZON {
+                    continue;
+                }
+                let closest_offset = relative_pos - relative_velocity * time_to_closest;
+                if closest_offset.length() >= COLLISION_DISTANCE {
+                    continue;
+                }
+
+                // Each side takes half of the correction. Dead on collisions sidestep to the
+                // right, which is the same rule for both animals so they never pick the same side.
+                let sidestep = if closest_offset.length_squared() > f32::EPSILON {
+                    -closest_offset.normalize()
+                } else {
+                    -preferred.perp()
+                };
+                let urgency = 1.0 - time_to_closest / AVOIDANCE_HORIZON;
+                avoidance += sidestep * urgency * 0.5;
+
+                let head_on = other_velocity
+                    .is_some_and(|other_velocity| preferred.dot(other_velocity.0) < HEAD_ON_DOT);
+                if head_on && other.id() < entity.id() {
+                    head_on_with_priority = true;
+                }
+            }
+
+            let mut direction =
+                (preferred + separation * SEPARATION_WEIGHT + avoidance * AVOIDANCE_WEIGHT)
+                    .normalize_or_zero();
+            // Making room is never worth turning around for
+            if direction.dot(preferred) < 0.0 {
+                direction = (direction + preferred).normalize_or_zero();
+            }
+            if direction == Vec2::ZERO {
+                direction = preferred;
+            }
+
+            // The path already goes around whatever the animal can't enter, don't steer into it
+            let mut speed_factor = 1.0;
+            let can_enter_ahead = |direction: Vec2| {
+                map.tile_at(pos.0 + direction * LOOKAHEAD_DISTANCE)
+                    .is_some_and(|tile| locomotion.can_enter(tile))
+            };
+            if !can_enter_ahead(direction) {
+                direction = preferred;
+                if head_on_with_priority {
+                    speed_factor = 0.0;
+                }
+            }
+
+            // Changed in place so that move_animals sees it this frame, only the first one has to
+            // wait for the insert
+            match steering {
+                Some(mut steering) => {
+                    steering.direction = direction;
+                    steering.speed_factor = speed_factor;
+                }
+                None => {
+                    commands.entity(entity).insert(Steering {
+                        direction,
+                        speed_factor,
+                    });
+                }
+            }
+        }
+    }
+}
@@ -3,20 +3,49 @@ use crate::prelude::*;
 pub struct VegetationPlugin;
 
 const INITIAL_PLANT_COUNT: i32 = 50;
+const MAX_PLANT_COUNT: usize = 120;
+const PLANT_SPAWN_INTERVAL: f32 = 3.0;
+// Seconds a plant lives before it withers away
+const MIN_PLANT_LIFESPAN: f32 = 120.0;
+const MAX_PLANT_LIFESPAN: f32 = 300.0;
+// Eaten plants never shrink below this fraction of their full size
+const MIN_PLANT_SCALE: f32 = 0.3;
 
 #[derive(Component)]
 pub struct Plant {
-    growth_period: i32,
-    plant_type: PlantType,
+    pub plant_type: PlantType,
+    pub nutrition: f32,
 }
 
+impl Plant {
+    pub fn new(plant_type: PlantType) -> Self {
+        Plant {
+            plant_type,
+            nutrition: plant_type.nutrition(),
+        }
+    }
+
+    pub fn blocks_sight(&self) -> bool {
+        self.plant_type.is_tall()
+    }
+
+    pub fn remaining_fraction(&self) -> f32 {
+        self.nutrition / self.plant_type.nutrition()
+    }
+}
+
+pub struct PlantSpawnCdf(pub Vec<f32>);
+
+pub struct PlantSpawnTimer(Timer);
+
 #[derive(Component)]
 pub struct ExistenceTimer(Timer);
 
+// Nutrition regrown per second
 #[derive(Component)]
 pub struct GrowthRate(f32);
 
-#[derive(EnumIter)] 
+#[derive(EnumIter, Clone, Copy, PartialEq, Eq, Debug)]
 pub enum PlantType {
     Anthurium,
     Daffidoil,
@@ -25,7 +54,7 @@ pub enum PlantType {
     Heather,
     Pansies,
     Proteam,
-    Scarletstar, 
+    Scarletstar,
 }
 
 impl PlantType {
@@ -42,7 +71,25 @@ impl PlantType {
         }
     }
 
-    pub fn cdf_array() -> Vec<f32>{
+    // How much hunger the whole plant takes away
+    pub fn nutrition(&self) -> f32 {
+        match self {
+            PlantType::Anthurium => 35.0,
+            PlantType::Daffidoil => 20.0,
+            PlantType::Foxglove => 10.0,
+            PlantType::Ginger => 30.0,
+            PlantType::Heather => 15.0,
+            PlantType::Pansies => 25.0,
+            PlantType::Proteam => 40.0,
+            PlantType::Scarletstar => 20.0,
+        }
+    }
+
+    pub fn is_tall(&self) -> bool {
+        matches!(self, PlantType::Foxglove | PlantType::Proteam)
+    }
+
+    pub fn cdf_array() -> Vec<f32> {
         let mut cdf_array: Vec<f32> = Vec::new();
         let mut acc: f32 = 0.0;
         for plant_type in PlantType::iter() {
@@ -52,27 +99,123 @@ impl PlantType {
 
         cdf_array
     }
+
+    pub fn from_cdf(cdf_array: &[f32]) -> Self {
+        let roll = thread_rng().gen_range(0.0..*cdf_array.last().unwrap());
+        PlantType::iter()
+            .zip(cdf_array.iter())
+            .find(|(_, acc)| roll < **acc)
+            .map(|(plant_type, _)| plant_type)
+            .unwrap_or(PlantType::Proteam)
+    }
 }
 
 impl Plugin for VegetationPlugin {
     fn build(&self, app: &mut App) {
         let cdf_array = PlantType::cdf_array();
-        app.insert_resource(resource)
+        app.insert_resource(PlantSpawnCdf(cdf_array))
+            .insert_resource(PlantSpawnTimer(Timer::from_seconds(
+                PLANT_SPAWN_INTERVAL,
+                true,
+            )))
+            .add_startup_system_to_stage(StartupStage::Startup, Self::spawn_initial_plants)
+            .add_system(Self::spawn_plants)
+            .add_system(Self::grow_plants)
+            .add_system(Self::wither_plants);
     }
 }
 
-impl VegetationPlugin { 
-    fn spawn_initial_plants(mut commands: Commands, map: Res<Map>) {
-        // let mut plant_counter = 0;
-        // let mut rng = thread_rng();
-        // while plant_counter < INITL_PLANT_COUNT {
-        //     let plant_pos_idx = rng.gen_range(0..map.size());
-        //     if map.tiles[plant_pos_idx].is_traversable() {
-        //         commands.spawn_bundle((
-        //             Plant,
-        //             ExistenceTimer(Timer::fro
-        //         ))
-        //     }
-        // }
+impl VegetationPlugin {
+    fn spawn_initial_plants(
+        mut commands: Commands,
+        map: Res<Map>,
+        cdf_array: Res<PlantSpawnCdf>,
+        sprite_sheets: Res<SpriteSheets>,
+    ) {
+        for _ in 0..INITIAL_PLANT_COUNT {
+            Self::spawn_plant(&mut commands, &map, &cdf_array, &sprite_sheets);
+        }
+    }
+
+    fn spawn_plants(
+        mut commands: Commands,
+        mut spawn_timer: ResMut<PlantSpawnTimer>,
+        plant_query: Query<&Plant>,
+        map: Res<Map>,
+        cdf_array: Res<PlantSpawnCdf>,
+        sprite_sheets: Res<SpriteSheets>,
+        time: Res<Time>,
+    ) {
+        spawn_timer.0.tick(time.delta());
+        if spawn_timer.0.just_finished() && plant_query.iter().count() < MAX_PLANT_COUNT {
+            Self::spawn_plant(&mut commands, &map, &cdf_array, &sprite_sheets);
+        }
+    }
+
+    fn spawn_plant(
+        commands: &mut Commands,
+        map: &Map,
+        cdf_array: &PlantSpawnCdf,
+        sprite_sheets: &SpriteSheets,
+    ) {
+        let mut rng = thread_rng();
+        let plant_pos_idx = loop {
+            let idx = rng.gen_range(0..map.size());
+            if map.tiles[idx].is_traversable() {
+                break idx;
+            }
+        };
+        let plant_pos = idx_to_vec2(plant_pos_idx as i32);
+        let plant_type = PlantType::from_cdf(&cdf_array.0);
+        let lifespan = rng.gen_range(MIN_PLANT_LIFESPAN..MAX_PLANT_LIFESPAN);
+
+        commands
+            .spawn_bundle(SpriteSheetBundle {
+                sprite: TextureAtlasSprite {
+                    custom_size: Some(GraphicsPlugin::get_plant_size(1.0)),
+                    index: plant_type as usize,
+                    ..default()
+                },
+                texture_atlas: sprite_sheets.plants.clone(),
+                transform: Transform {
+                    translation: Vec3::new(plant_pos.x, plant_pos.y, 0.0),
+                    ..default()
+                },
+                ..default()
+            })
+            .insert_bundle((
+                Plant::new(plant_type),
+                Pos(plant_pos),
+                GrowthRate(plant_type.nutrition() / lifespan),
+                ExistenceTimer(Timer::from_seconds(lifespan, false)),
+            ));
+    }
+
+    // Partly eaten plants slowly grow back
+    fn grow_plants(
+        mut query: Query<(&mut Plant, &GrowthRate, &mut TextureAtlasSprite)>,
+        time: Res<Time>,
+    ) {
+        for (mut plant, growth_rate, mut sprite) in query.iter_mut() {
+            let max_nutrition = plant.plant_type.nutrition();
+            plant.nutrition =
+                (plant.nutrition + growth_rate.0 * time.delta_seconds()).min(max_nutrition);
+            sprite.custom_size = Some(GraphicsPlugin::get_plant_size(
+                plant.remaining_fraction().max(MIN_PLANT_SCALE),
+            ));
+        }
+    }
+
+    fn wither_plants(
+        mut query: Query<(Entity, &mut ExistenceTimer)>,
+        time: Res<Time>,
+        mut commands: Commands,
+    ) {
+        for (entity, mut existence_timer) in query.iter_mut() {
+            existence_timer.0.tick(time.delta());
+            if existence_timer.0.finished() {
+                commands.entity(entity).despawn();
+            }
+        }
     }
 }
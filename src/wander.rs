@@ -0,0 +1,360 @@
+use bevy::utils::HashMap;
+
+use crate::prelude::*;
+
+// In tiles
+const WANDER_RADIUS: f32 = 12.0;
+const WANDER_CANDIDATES: usize = 8;
+const WANDER_SEARCH_BUDGET: usize = 600;
+const EXPLORATION_CELL_SIZE: f32 = (TILE_SIZE * 5) as f32;
+const RESOURCE_ATTRACTION_RADIUS: f32 = (TILE_SIZE * 6) as f32;
+// Remembered predators make the places around where they were seen less attractive
+const DANGER_RADIUS: f32 = (TILE_SIZE * 10) as f32;
+const MIN_WANDER_PAUSE: f32 = 1.0;
+const MAX_WANDER_PAUSE: f32 = 4.0;
+const HOME_RANGE_LOG_INTERVAL: f32 = 30.0;
+
+const NOVELTY_WEIGHT: f32 = 1.0;
+const RESOURCE_WEIGHT: f32 = 0.75;
+const RANDOM_WEIGHT: f32 = 0.5;
+const DANGER_WEIGHT: f32 = 1.5;
+
+pub struct WanderPlugin;
+
+impl Plugin for WanderPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(HomeRangeLogTimer(Timer::from_seconds(
+            HOME_RANGE_LOG_INTERVAL,
+            true,
+        )))
+        .add_system(Self::record_exploration)
+        .add_system(Self::wander)
+        .add_system(Self::log_home_ranges);
+    }
+}
+
+#[derive(Component, Clone, Copy)]
+pub struct HomeRange {
+    pub center: Vec2,
+    // In world units
+    pub radius: f32,
+}
+
+// How many times the animal has entered each coarse cell of the map
+#[derive(Component, Default)]
+pub struct Exploration {
+    visits: HashMap<(i32, i32), u32>,
+    last_cell: Option<(i32, i32)>,
+}
+
+impl Exploration {
+    pub fn visits(&self, pos: Vec2) -> u32 {
+        self.visits
+            .get(&exploration_cell(pos))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    // Area of the smallest convex polygon around every cell the animal has been in, the usual
+    // minimum convex polygon estimate of a home range. In square tiles.
+    pub fn home_range_area(&self) -> f32 {
+        let corners: Vec<Vec2> = self
+            .visits
+            .keys()
+            .flat_map(|(x, y)| {
+                let corner = Vec2::new(*x as f32, *y as f32) * EXPLORATION_CELL_SIZE;
+                [
+                    corner,
+                    corner + Vec2::new(EXPLORATION_CELL_SIZE, 0.0),
+                    corner + Vec2::new(0.0, EXPLORATION_CELL_SIZE),
+                    corner + Vec2::splat(EXPLORATION_CELL_SIZE),
+                ]
+            })
+            .collect();
+        polygon_area(&convex_hull(corners)) / (TILE_SIZE * TILE_SIZE) as f32
+    }
+}
+
+struct HomeRangeLogTimer(Timer);
+
+// Time to stand around once the current wander target is reached
+#[derive(Component)]
+pub struct WanderPause(pub Timer);
+
+// Andrew's monotone chain, counter-clockwise
+fn convex_hull(mut points: Vec<Vec2>) -> Vec<Vec2> {
+    points.sort_by(|a, b| {
+        (a.x, a.y)
+            .partial_cmp(&(b.x, b.y))
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    points.dedup();
+    if points.len() < 3 {
+        return points;
+    }
+
+    let turns_left = |hull: &[Vec2], point: Vec2| {
+        let (a, b) = (hull[hull.len() - 2], hull[hull.len() - 1]);
+        (b - a).perp_dot(point - a) > 0.0
+    };
+    let mut hull: Vec<Vec2> = Vec::new();
+    for pass in [points.clone(), points.into_iter().rev().collect()] {
+        let start = hull.len();
+        for point in pass {
+            while hull.len() >= start + 2 && !turns_left(&hull, point) {
+                hull.pop();
+            }
+            hull.push(point);
+        }
+        // The last point of each half is the first of the other one
+        hull.pop();
+    }
+    hull
+}
+
+// Shoelace formula
+fn polygon_area(polygon: &[Vec2]) -> f32 {
+    let doubled_area: f32 = (0..polygon.len())
+        .map(|i| polygon[i].perp_dot(polygon[(i + 1) % polygon.len()]))
+        .sum();
+    doubled_area.abs() / 2.0
+}
+
+fn exploration_cell(pos: Vec2) -> (i32, i32) {
+    (
+        (pos.x / EXPLORATION_CELL_SIZE).floor() as i32,
+        (pos.y / EXPLORATION_CELL_SIZE).floor() as i32,
+    )
+}
+
+impl WanderPlugin {
+    fn record_exploration(mut query: Query<(&Pos, &mut Exploration)>) {
+        for (pos, mut exploration) in query.iter_mut() {
+            let cell = exploration_cell(pos.0);
+            if exploration.last_cell != Some(cell) {
+                exploration.last_cell = Some(cell);
+                *exploration.visits.entry(cell).or_insert(0) += 1;
+            }
+        }
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn wander(
+        mut query: Query<
+            (
+                Entity,
+                &Pos,
+                &Locomotion,
+                &Decision,
+                &HomeRange,
+                &Exploration,
+                &Perceived,
+                &Memory,
+                Option<&mut WanderPause>,
+                Option<&Herd>,
+            ),
+            (
+                Without<Path>,
+                Without<PathRequest>,
+                Without<PathfindingTask>,
+            ),
+        >,
+        map: Res<Map>,
+        time: Res<Time>,
+        mut commands: Commands,
+    ) {
+        for (
+            entity,
+            pos,
+            locomotion,
+            decision,
+            home_range,
+            exploration,
+            perceived,
+            memory,
+            pause,
+            herd,
+        ) in query.iter_mut()
+        {
+            // Only the herd leader picks where to go, the rest of the herd follows it
+            let follows_herd = herd.is_some_and(|herd| !herd.is_leader(entity));
+            if decision.action != Action::Wander || follows_herd {
+                continue;
+            }
+
+            if let Some(mut pause) = pause {
+                pause.0.tick(time.delta());
+                if !pause.0.finished() {
+                    continue;
+                }
+            }
+
+            let mut candidates: VecDeque<Vec2> = Self::score_wander_targets(
+                pos.0,
+                *locomotion,
+                home_range,
+                exploration,
+                perceived,
+                memory,
+                &map,
+            )
+            .into_iter()
+            .map(|(target, _)| target)
+            .collect();
+            // The next best targets get tried in turn when there's no path to the best one
+            if let Some(target) = candidates.pop_front() {
+                let pause_duration = thread_rng().gen_range(MIN_WANDER_PAUSE..MAX_WANDER_PAUSE);
+                commands
+                    .entity(entity)
+                    .insert(PathRequest {
+                        goal: target,
+                        options: PathOptions {
+                            max_nodes: Some(WANDER_SEARCH_BUDGET),
+                            locomotion: *locomotion,
+                            ..default()
+                        },
+                    })
+                    .insert(FallbackGoals(candidates))
+                    .insert(WanderPause(Timer::from_seconds(pause_duration, false)));
+            }
+        }
+    }
+
+    fn log_home_ranges(
+        query: Query<(&AnimalType, &Exploration)>,
+        mut timer: ResMut<HomeRangeLogTimer>,
+        species: Res<SpeciesRegistry>,
+        time: Res<Time>,
+    ) {
+        timer.0.tick(time.delta());
+        if !timer.0.just_finished() {
+            return;
+        }
+
+        let mut areas: HashMap<AnimalType, Vec<f32>> = HashMap::new();
+        for (animal_type, exploration) in query.iter() {
+            areas
+                .entry(*animal_type)
+                .or_default()
+                .push(exploration.home_range_area());
+        }
+        for (animal_type, areas) in areas.iter() {
+            let mean = areas.iter().sum::<f32>() / areas.len() as f32;
+            let largest = areas.iter().copied().fold(0.0, f32::max);
+            println!(
+                "{} home ranges: mean {:.0} tiles, largest {:.0} tiles",
+                species.name(*animal_type),
+                mean,
+                largest
+            );
+        }
+    }
+
+    // Random nearby tiles, best first. Unexplored cells and places with food or water around
+    // them score higher, places where predators were seen lower, anything outside the home range
+    // is left out.
+    fn score_wander_targets(
+        pos: Vec2,
+        locomotion: Locomotion,
+        home_range: &HomeRange,
+        exploration: &Exploration,
+        perceived: &Perceived,
+        memory: &Memory,
+        map: &Map,
+    ) -> Vec<(Vec2, f32)> {
+        let mut rng = thread_rng();
+        let outside_home_range = (pos - home_range.center).length() > home_range.radius;
+        let mut candidates: Vec<(Vec2, f32)> = Vec::new();
+
+        for _ in 0..WANDER_CANDIDATES {
+            let offset = Vec2::new(
+                rng.gen_range(-WANDER_RADIUS..WANDER_RADIUS),
+                rng.gen_range(-WANDER_RADIUS..WANDER_RADIUS),
+            ) * TILE_SIZE as f32;
+            let target = pos + offset;
+            if !map
+                .tile_at(target)
+                .is_some_and(|tile| locomotion.can_enter(tile))
+            {
+                continue;
+            }
+
+            let distance_from_home = (target - home_range.center).length();
+            if !outside_home_range && distance_from_home > home_range.radius {
+                continue;
+            }
+
+            let novelty = 1.0 / (1.0 + exploration.visits(target) as f32);
+            let nearby_resources = perceived
+                .plants
+                .iter()
+                .map(|(_, plant_pos)| *plant_pos)
+                .chain(perceived.water.iter().copied())
+                .filter(|resource_pos| {
+                    (*resource_pos - target).length() <= RESOURCE_ATTRACTION_RADIUS
+                })
+                .count();
+            let resources = (nearby_resources as f32 / 5.0).min(1.0);
+            let danger: f32 = memory
+                .recall(MemoryKind::Predator)
+                .filter(|predator| (predator.pos - target).length() <= DANGER_RADIUS)
+                .map(|predator| predator.confidence)
+                .sum();
+            let mut score = NOVELTY_WEIGHT * novelty + RESOURCE_WEIGHT * resources
+                - DANGER_WEIGHT * danger.min(1.0)
+                + rng.gen_range(0.0..RANDOM_WEIGHT);
+            // Strayed too far, so head back towards the middle of the home range
+            if outside_home_range {
+                score -= distance_from_home / home_range.radius;
+            }
+            candidates.push((target, score));
+        }
+
+        candidates.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+        candidates
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hull_of_a_square_leaves_out_the_inner_points() {
+        let hull = convex_hull(vec![
+            Vec2::new(0.0, 0.0),
+            Vec2::new(2.0, 0.0),
+            Vec2::new(1.0, 1.0),
+            Vec2::new(0.0, 2.0),
+            Vec2::new(2.0, 2.0),
+            Vec2::new(1.0, 0.0),
+        ]);
+        assert_eq!(
+            hull,
+            vec![
+                Vec2::new(0.0, 0.0),
+                Vec2::new(2.0, 0.0),
+                Vec2::new(2.0, 2.0),
+                Vec2::new(0.0, 2.0),
+            ]
+        );
+        assert_eq!(polygon_area(&hull), 4.0);
+    }
+
+    #[test]
+    fn area_does_not_depend_on_winding() {
+        let triangle = [
+            Vec2::new(0.0, 0.0),
+            Vec2::new(0.0, 3.0),
+            Vec2::new(4.0, 0.0),
+        ];
+        assert_eq!(polygon_area(&triangle), 6.0);
+    }
+
+    #[test]
+    fn hull_of_fewer_than_three_points_has_no_area() {
+        let hull = convex_hull(vec![Vec2::new(1.0, 1.0), Vec2::new(1.0, 1.0)]);
+        assert_eq!(hull, vec![Vec2::new(1.0, 1.0)]);
+        assert_eq!(polygon_area(&hull), 0.0);
+    }
+}